@@ -0,0 +1,852 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+use ical::{parser::ical::component::IcalEvent, property::Property, IcalParser};
+use regex::Regex;
+
+use crate::{config::ConfigCountdownCalendar, countdown::CountdownEvent};
+
+/// Recurrences are expanded for at most this many periods (days, weeks, months or years), so a
+/// rule that never produces another occurrence can't loop forever.
+const MAX_RECURRENCE_PERIODS: i64 = 100_000;
+
+/// The timezone a date-time in the calendar is in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Zone {
+    Utc,
+    Named(Tz),
+    /// Wall-clock time wherever the calendar is viewed
+    Floating,
+}
+
+impl Zone {
+    fn from_tzid(tzid: &str) -> Zone {
+        let tzid = tzid.trim_matches('"');
+        // Some exporters prefix the IANA name, e.g. /mozilla.org/20050126_1/Europe/London
+        let candidates = std::iter::once(tzid).chain(
+            tzid.match_indices('/')
+                .map(|(i, _)| &tzid[i + 1..])
+                .filter(|suffix| !suffix.is_empty()),
+        );
+        for candidate in candidates {
+            if let Ok(tz) = Tz::from_str(candidate) {
+                return Zone::Named(tz);
+            }
+        }
+        log::warn!(
+            "Unknown timezone {} in calendar; treating its times as local",
+            tzid
+        );
+        Zone::Floating
+    }
+
+    /// The instant of `naive` in this zone. A time skipped by a daylight saving change is moved
+    /// forward by an hour, as RFC 5545 specifies.
+    fn resolve(self, naive: NaiveDateTime) -> Option<DateTime<Local>> {
+        fn earliest<Z: TimeZone>(zone: &Z, naive: NaiveDateTime) -> Option<DateTime<Local>> {
+            zone.from_local_datetime(&naive)
+                .earliest()
+                .or_else(|| {
+                    zone.from_local_datetime(&(naive + Duration::hours(1)))
+                        .earliest()
+                })
+                .map(|datetime| datetime.with_timezone(&Local))
+        }
+        match self {
+            Zone::Utc => Some(Utc.from_utc_datetime(&naive).with_timezone(&Local)),
+            Zone::Named(tz) => earliest(&tz, naive),
+            Zone::Floating => earliest(&Local, naive),
+        }
+    }
+}
+
+/// A DATE or DATE-TIME value, before it is resolved to an instant.
+#[derive(Debug, Clone, Copy)]
+struct CalendarTime {
+    naive: NaiveDateTime,
+    zone: Zone,
+    /// Whether this is a whole day (`VALUE=DATE`), which starts at midnight
+    is_date: bool,
+}
+
+impl CalendarTime {
+    fn parse(value: &str, tzid: Option<&str>) -> anyhow::Result<Self> {
+        let value = value.trim();
+        if value.len() == 8 {
+            let date = NaiveDate::parse_from_str(value, "%Y%m%d")
+                .with_context(|| format!("Invalid date {}", value))?;
+            return Ok(CalendarTime {
+                naive: date.and_time(NaiveTime::MIN),
+                zone: tzid.map(Zone::from_tzid).unwrap_or(Zone::Floating),
+                is_date: true,
+            });
+        }
+        let (value, zone) = match value.strip_suffix('Z') {
+            Some(value) => (value, Zone::Utc),
+            None => (value, tzid.map(Zone::from_tzid).unwrap_or(Zone::Floating)),
+        };
+        Ok(CalendarTime {
+            naive: NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+                .with_context(|| format!("Invalid date-time {}", value))?,
+            zone,
+            is_date: false,
+        })
+    }
+
+    fn resolve(self) -> Option<DateTime<Local>> {
+        self.zone.resolve(self.naive)
+    }
+}
+
+fn param<'a>(property: &'a Property, name: &str) -> Option<&'a str> {
+    property
+        .params
+        .as_ref()?
+        .iter()
+        .find(|(param_name, _)| param_name.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+fn properties<'a>(event: &'a IcalEvent, name: &'a str) -> impl Iterator<Item = &'a Property> {
+    event
+        .properties
+        .iter()
+        .filter(move |property| property.name.eq_ignore_ascii_case(name))
+}
+
+fn value<'a>(event: &'a IcalEvent, name: &str) -> Option<&'a str> {
+    event
+        .properties
+        .iter()
+        .find(|property| property.name.eq_ignore_ascii_case(name))
+        .and_then(|property| property.value.as_deref())
+}
+
+/// Every (comma-separated) DATE or DATE-TIME value of a property.
+fn times(property: &Property) -> anyhow::Result<Vec<CalendarTime>> {
+    let tzid = param(property, "TZID");
+    property
+        .value
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| CalendarTime::parse(value, tzid))
+        .collect()
+}
+
+/// Undoes the escaping of a TEXT value.
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => unescaped.push('\\'),
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+/// Splits a TEXT list on commas that aren't escaped.
+fn split_text_list(text: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut escaped = false;
+    for c in text.chars() {
+        if c == ',' && !escaped {
+            items.push(String::new());
+        } else {
+            items.last_mut().unwrap().push(c);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    items
+        .iter()
+        .map(|item| unescape_text(item.trim()))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A recurrence rule (RRULE). Only the commonly exported parts of RFC 5545 are supported.
+#[derive(Debug)]
+struct Rule {
+    frequency: Frequency,
+    interval: i64,
+    count: Option<u32>,
+    until: Option<DateTime<Local>>,
+    /// Weekdays, each optionally the n-th (or n-th last, if negative) of the month
+    by_day: Vec<(Option<i32>, Weekday)>,
+    /// Days of the month, counted from the end if negative
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+}
+
+fn parse_weekday(s: &str) -> anyhow::Result<Weekday> {
+    Ok(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => bail!("Invalid weekday {}", s),
+    })
+}
+
+fn parse_list<T: FromStr>(value: &str, part: &str) -> anyhow::Result<Vec<T>> {
+    value
+        .split(',')
+        .map(|item| {
+            item.trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid {} {}", part, item))
+        })
+        .collect()
+}
+
+impl Rule {
+    fn parse(rule: &str, start: CalendarTime) -> anyhow::Result<Self> {
+        let mut frequency = None;
+        let mut parsed = Rule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+        };
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .with_context(|| format!("Invalid part {}", part))?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => bail!("Unsupported frequency {}", value),
+                    })
+                }
+                "INTERVAL" => {
+                    parsed.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .with_context(|| format!("Invalid interval {}", value))?
+                }
+                "COUNT" => {
+                    parsed.count = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("Invalid count {}", value))?,
+                    )
+                }
+                "UNTIL" => {
+                    let mut until = CalendarTime::parse(value, None)?;
+                    if until.zone == Zone::Floating {
+                        until.zone = start.zone;
+                    }
+                    if until.is_date {
+                        // The whole of the last day is included
+                        until.naive = until.naive.date().and_time(NaiveTime::MIN)
+                            + Duration::days(1)
+                            - Duration::seconds(1);
+                    }
+                    parsed.until = until.resolve();
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let day = day.trim().to_ascii_uppercase();
+                        let (ordinal, weekday) = day.split_at(day.len().saturating_sub(2));
+                        let ordinal = match ordinal {
+                            "" => None,
+                            ordinal => Some(
+                                ordinal
+                                    .trim_start_matches('+')
+                                    .parse()
+                                    .with_context(|| format!("Invalid weekday {}", day))?,
+                            ),
+                        };
+                        parsed.by_day.push((ordinal, parse_weekday(weekday)?));
+                    }
+                }
+                "BYMONTHDAY" => parsed.by_month_day = parse_list(value, "day of the month")?,
+                "BYMONTH" => parsed.by_month = parse_list(value, "month")?,
+                "WKST" => {}
+                _ => bail!("Unsupported part {}", name),
+            }
+        }
+        parsed.frequency = frequency.context("FREQ is missing")?;
+
+        let has_ordinals = parsed.by_day.iter().any(|(ordinal, _)| ordinal.is_some());
+        if has_ordinals && matches!(parsed.frequency, Frequency::Daily | Frequency::Weekly) {
+            bail!("Numbered weekdays in BYDAY need FREQ=MONTHLY or FREQ=YEARLY");
+        }
+        if parsed.frequency == Frequency::Yearly
+            && !parsed.by_day.is_empty()
+            && parsed.by_month.is_empty()
+        {
+            bail!("BYDAY with FREQ=YEARLY is only supported together with BYMONTH");
+        }
+        Ok(parsed)
+    }
+
+    fn matches_month_day(&self, date: NaiveDate) -> bool {
+        let days_in_month = days_in_month(date.year(), date.month()) as i32;
+        self.by_month_day.is_empty()
+            || self.by_month_day.iter().any(|day| {
+                let day = if *day < 0 {
+                    days_in_month + 1 + day
+                } else {
+                    *day
+                };
+                day == date.day() as i32
+            })
+    }
+
+    fn matches_weekday(&self, date: NaiveDate) -> bool {
+        self.by_day.is_empty()
+            || self
+                .by_day
+                .iter()
+                .any(|(_, weekday)| *weekday == date.weekday())
+    }
+
+    fn matches_month(&self, date: NaiveDate) -> bool {
+        self.by_month.is_empty() || self.by_month.contains(&date.month())
+    }
+
+    /// The days of a month the rule recurs on, in order.
+    fn days_of_month(&self, year: i32, month: u32, start: NaiveDate) -> Vec<NaiveDate> {
+        let month_dates: Vec<NaiveDate> = (1..=days_in_month(year, month))
+            .filter_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+            .collect();
+        let mut dates: Vec<NaiveDate> = if !self.by_month_day.is_empty() {
+            month_dates
+                .into_iter()
+                .filter(|date| self.matches_month_day(*date) && self.matches_weekday(*date))
+                .collect()
+        } else if !self.by_day.is_empty() {
+            self.by_day
+                .iter()
+                .flat_map(|(ordinal, weekday)| {
+                    let weekdays: Vec<NaiveDate> = month_dates
+                        .iter()
+                        .copied()
+                        .filter(|date| date.weekday() == *weekday)
+                        .collect();
+                    match ordinal {
+                        None => weekdays,
+                        Some(n) if *n > 0 => {
+                            weekdays.get(*n as usize - 1).copied().into_iter().collect()
+                        }
+                        Some(n) => weekdays
+                            .len()
+                            .checked_sub(n.unsigned_abs() as usize)
+                            .map(|i| weekdays[i])
+                            .into_iter()
+                            .collect(),
+                    }
+                })
+                .collect()
+        } else {
+            NaiveDate::from_ymd_opt(year, month, start.day())
+                .into_iter()
+                .collect()
+        };
+        dates.sort();
+        dates.dedup();
+        dates
+    }
+
+    /// The days the rule recurs on in the `index`-th period after the one `start` is in, in
+    /// order.
+    fn dates_in_period(&self, start: NaiveDate, index: i64) -> Vec<NaiveDate> {
+        let steps = index * self.interval;
+        match self.frequency {
+            Frequency::Daily => {
+                let date = start + Duration::days(steps);
+                if self.matches_month(date)
+                    && self.matches_month_day(date)
+                    && self.matches_weekday(date)
+                {
+                    vec![date]
+                } else {
+                    vec![]
+                }
+            }
+            Frequency::Weekly => {
+                let week_start = start
+                    - Duration::days(start.weekday().num_days_from_monday() as i64)
+                    + Duration::weeks(steps);
+                let mut dates: Vec<NaiveDate> = if self.by_day.is_empty() {
+                    vec![week_start + Duration::days(start.weekday().num_days_from_monday() as i64)]
+                } else {
+                    self.by_day
+                        .iter()
+                        .map(|(_, weekday)| {
+                            week_start + Duration::days(weekday.num_days_from_monday() as i64)
+                        })
+                        .collect()
+                };
+                dates.retain(|date| self.matches_month(*date));
+                dates.sort();
+                dates.dedup();
+                dates
+            }
+            Frequency::Monthly => {
+                let months = start.year() as i64 * 12 + start.month0() as i64 + steps;
+                let (year, month) = ((months / 12) as i32, (months % 12) as u32 + 1);
+                if self.by_month.is_empty() || self.by_month.contains(&month) {
+                    self.days_of_month(year, month, start)
+                } else {
+                    vec![]
+                }
+            }
+            Frequency::Yearly => {
+                let year = start.year() + steps as i32;
+                let months: Vec<u32> = if !self.by_month.is_empty() {
+                    let mut months = self.by_month.clone();
+                    months.sort();
+                    months
+                } else if !self.by_month_day.is_empty() {
+                    (1..=12).collect()
+                } else {
+                    vec![start.month()]
+                };
+                months
+                    .into_iter()
+                    .flat_map(|month| self.days_of_month(year, month, start))
+                    .collect()
+            }
+        }
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|first_of_next| first_of_next.pred_opt())
+        .map(|last| last.day())
+        .unwrap_or(31)
+}
+
+/// A VEVENT, with the parts needed to find its occurrences.
+struct Event {
+    summary: String,
+    start: CalendarTime,
+    rule: Option<Rule>,
+    extra_starts: Vec<DateTime<Local>>,
+    excluded_starts: Vec<DateTime<Local>>,
+}
+
+impl Event {
+    /// The first occurrence that starts after `after`.
+    fn next_start_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let from_rule = match &self.rule {
+            None => self.start.resolve().filter(|start| *start > after),
+            Some(rule) => self.next_start_from_rule(rule, after),
+        };
+        let from_extra = self
+            .extra_starts
+            .iter()
+            .copied()
+            .filter(|start| *start > after && !self.excluded_starts.contains(start))
+            .min();
+        from_rule.into_iter().chain(from_extra).min()
+    }
+
+    fn next_start_from_rule(&self, rule: &Rule, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start_date = self.start.naive.date();
+        let start_time = self.start.naive.time();
+        let mut count = 0;
+        for index in 0..MAX_RECURRENCE_PERIODS {
+            for date in rule.dates_in_period(start_date, index) {
+                let naive = date.and_time(start_time);
+                if naive < self.start.naive {
+                    continue;
+                }
+                // COUNT includes excluded occurrences
+                count += 1;
+                if rule.count.is_some_and(|max| count > max) {
+                    return None;
+                }
+                let Some(start) = self.start.zone.resolve(naive) else {
+                    continue;
+                };
+                if rule.until.is_some_and(|until| start > until) {
+                    return None;
+                }
+                if start > after && !self.excluded_starts.contains(&start) {
+                    return Some(start);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Parses the VEVENTs of a calendar for which `keep` (given the summary and categories) is true,
+/// removing cancelled events and overridden occurrences. Events that can't be parsed (e.g. with
+/// an unsupported RRULE) are skipped with a warning, rather than failing the whole calendar.
+fn read_events(
+    reader: impl BufRead,
+    keep: impl Fn(&str, &[String]) -> bool,
+) -> anyhow::Result<Vec<Event>> {
+    let mut ical_events = Vec::new();
+    for calendar in IcalParser::new(reader) {
+        ical_events.extend(calendar.context("Failed to parse calendar")?.events);
+    }
+
+    // Occurrences moved or cancelled by an event with a RECURRENCE-ID, by UID
+    let mut overridden: Vec<(String, DateTime<Local>)> = Vec::new();
+    for event in &ical_events {
+        if let (Some(uid), Some(recurrence_id)) = (
+            value(event, "UID"),
+            properties(event, "RECURRENCE-ID").next(),
+        ) {
+            match times(recurrence_id) {
+                Ok(times) => overridden.extend(
+                    times
+                        .into_iter()
+                        .filter_map(|time| time.resolve().map(|start| (uid.to_string(), start))),
+                ),
+                Err(e) => log::warn!("Skipping invalid RECURRENCE-ID of event {}: {:#}", uid, e),
+            }
+        }
+    }
+
+    let mut events = Vec::new();
+    for event in &ical_events {
+        if value(event, "STATUS").is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED")) {
+            continue;
+        }
+        let summary = unescape_text(value(event, "SUMMARY").unwrap_or_default());
+        let categories: Vec<String> = properties(event, "CATEGORIES")
+            .flat_map(|property| split_text_list(property.value.as_deref().unwrap_or_default()))
+            .collect();
+        if !keep(&summary, &categories) {
+            continue;
+        }
+        let parse = || -> anyhow::Result<Event> {
+            let start = properties(event, "DTSTART")
+                .next()
+                .map(times)
+                .transpose()?
+                .and_then(|starts| starts.first().copied())
+                .context("DTSTART is missing")?;
+            let rule = value(event, "RRULE")
+                .map(|rule| {
+                    Rule::parse(rule, start).with_context(|| format!("Invalid RRULE {}", rule))
+                })
+                .transpose()?;
+            let resolved_times = |name| -> anyhow::Result<Vec<DateTime<Local>>> {
+                let mut resolved = Vec::new();
+                for property in properties(event, name) {
+                    resolved.extend(
+                        times(property)?
+                            .into_iter()
+                            .filter_map(CalendarTime::resolve),
+                    );
+                }
+                Ok(resolved)
+            };
+            let mut excluded_starts = resolved_times("EXDATE")?;
+            let is_override = properties(event, "RECURRENCE-ID").next().is_some();
+            if let (Some(uid), false) = (value(event, "UID"), is_override) {
+                excluded_starts.extend(
+                    overridden
+                        .iter()
+                        .filter(|(overridden_uid, _)| overridden_uid == uid)
+                        .map(|(_, start)| *start),
+                );
+            }
+            Ok(Event {
+                summary: summary.clone(),
+                start,
+                rule,
+                extra_starts: resolved_times("RDATE")?,
+                excluded_starts,
+            })
+        };
+        match parse() {
+            Ok(parsed) => events.push(parsed),
+            Err(e) => log::warn!("Skipping event {} in calendar: {:#}", summary, e),
+        }
+    }
+    Ok(events)
+}
+
+/// The summary and start of the first event that matches the filters and starts after `now`.
+fn next_matching_event(
+    reader: impl BufRead,
+    config: &ConfigCountdownCalendar,
+    now: DateTime<Local>,
+) -> anyhow::Result<Option<(String, DateTime<Local>)>> {
+    let summary_regex = config
+        .summary
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .context("Invalid summary regex")?;
+    let keep = |summary: &str, categories: &[String]| {
+        config.category.as_ref().is_none_or(|category| {
+            categories
+                .iter()
+                .any(|event_category| event_category.eq_ignore_ascii_case(category))
+        }) && summary_regex
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(summary))
+    };
+    Ok(read_events(reader, keep)?
+        .into_iter()
+        .filter_map(|event| {
+            let start = event.next_start_after(now)?;
+            Some((event.summary, start))
+        })
+        .min_by_key(|(_, start)| *start))
+}
+
+/// The next event in the `[[countdowns.calendars]]` file that matches its filters, if any.
+pub(crate) fn next_calendar_event(
+    config: &ConfigCountdownCalendar,
+    now: DateTime<Local>,
+) -> anyhow::Result<Option<CountdownEvent>> {
+    let file = File::open(&config.path)
+        .with_context(|| format!("Failed to open calendar {}", config.path))?;
+    let next = next_matching_event(BufReader::new(file), config, now)
+        .with_context(|| format!("Failed to read calendar {}", config.path))?;
+    Ok(next.map(|(summary, target)| CountdownEvent {
+        label: config.label.clone().unwrap_or(summary),
+        target,
+        start: config
+            .ramp_days
+            .map(|days| target - Duration::days(days as i64)),
+        counting: config.counting,
+        style: config.style,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CountdownStyle, DayCounting};
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Local)
+    }
+
+    fn calendar(events: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//test//EN\r\n{}END:VCALENDAR\r\n",
+            events
+        )
+    }
+
+    fn filter(category: Option<&str>, summary: Option<&str>) -> ConfigCountdownCalendar {
+        ConfigCountdownCalendar {
+            path: String::new(),
+            label: None,
+            category: category.map(String::from),
+            summary: summary.map(String::from),
+            ramp_days: None,
+            counting: DayCounting::Calendar,
+            style: CountdownStyle::Number,
+        }
+    }
+
+    fn next(
+        events: &str,
+        config: &ConfigCountdownCalendar,
+        now: DateTime<Local>,
+    ) -> Option<(String, DateTime<Local>)> {
+        next_matching_event(calendar(events).as_bytes(), config, now).unwrap()
+    }
+
+    fn next_start(events: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        next(events, &filter(None, None), now).map(|(_, start)| start)
+    }
+
+    #[test]
+    fn single_event_in_named_timezone() {
+        let events = "BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Essay\\, part 1\r\n\
+                      DTSTART;TZID=America/New_York:20240520T090000\r\nEND:VEVENT\r\n";
+        assert_eq!(
+            next(events, &filter(None, None), utc(2024, 5, 1, 0, 0)),
+            Some((String::from("Essay, part 1"), utc(2024, 5, 20, 13, 0)))
+        );
+        assert_eq!(next_start(events, utc(2024, 5, 20, 13, 0)), None);
+    }
+
+    #[test]
+    fn prefixed_timezone_and_all_day_event() {
+        let events = "BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Talk\r\n\
+                      DTSTART;TZID=/mozilla.org/20050126_1/Asia/Tokyo:20240520T090000\r\n\
+                      END:VEVENT\r\n";
+        assert_eq!(
+            next_start(events, utc(2024, 5, 1, 0, 0)),
+            Some(utc(2024, 5, 20, 0, 0))
+        );
+        let events = "BEGIN:VEVENT\r\nUID:2\r\nSUMMARY:Holiday\r\n\
+                      DTSTART;VALUE=DATE:20240520\r\nEND:VEVENT\r\n";
+        let expected = Local
+            .from_local_datetime(
+                &NaiveDate::from_ymd_opt(2024, 5, 20)
+                    .unwrap()
+                    .and_time(NaiveTime::MIN),
+            )
+            .unwrap();
+        assert_eq!(next_start(events, utc(2024, 5, 1, 0, 0)), Some(expected));
+    }
+
+    #[test]
+    fn weekly_rule_keeps_wall_clock_time_across_daylight_saving() {
+        // Clocks went forward in London on 31 March 2024
+        let events = "BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Lecture\r\n\
+                      DTSTART;TZID=Europe/London:20240318T090000\r\n\
+                      RRULE:FREQ=WEEKLY;BYDAY=MO,WE\r\nEND:VEVENT\r\n";
+        assert_eq!(
+            next_start(events, utc(2024, 3, 18, 9, 0)),
+            Some(utc(2024, 3, 20, 9, 0))
+        );
+        assert_eq!(
+            next_start(events, utc(2024, 4, 2, 0, 0)),
+            Some(utc(2024, 4, 3, 8, 0))
+        );
+    }
+
+    #[test]
+    fn monthly_rule_with_numbered_weekday_and_count() {
+        let events = "BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Report\r\nDTSTART:20240126T170000Z\r\n\
+                      RRULE:FREQ=MONTHLY;BYDAY=-1FR;COUNT=3\r\nEND:VEVENT\r\n";
+        assert_eq!(
+            next_start(events, utc(2024, 3, 1, 0, 0)),
+            Some(utc(2024, 3, 29, 17, 0))
+        );
+        assert_eq!(next_start(events, utc(2024, 3, 29, 17, 0)), None);
+    }
+
+    #[test]
+    fn monthly_rule_with_interval_and_month_day() {
+        let events = "BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Rent\r\nDTSTART:20240131T090000Z\r\n\
+                      RRULE:FREQ=MONTHLY;INTERVAL=2;BYMONTHDAY=-1\r\nEND:VEVENT\r\n";
+        assert_eq!(
+            next_start(events, utc(2024, 2, 1, 0, 0)),
+            Some(utc(2024, 3, 31, 9, 0))
+        );
+        assert_eq!(
+            next_start(events, utc(2024, 4, 1, 0, 0)),
+            Some(utc(2024, 5, 31, 9, 0))
+        );
+    }
+
+    #[test]
+    fn yearly_rule_skips_missing_days_until_end() {
+        let events = "BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Leap day\r\nDTSTART:20200229T120000Z\r\n\
+                      RRULE:FREQ=YEARLY;UNTIL=20280229\r\nEND:VEVENT\r\n";
+        assert_eq!(
+            next_start(events, utc(2021, 1, 1, 0, 0)),
+            Some(utc(2024, 2, 29, 12, 0))
+        );
+        assert_eq!(
+            next_start(events, utc(2024, 3, 1, 0, 0)),
+            Some(utc(2028, 2, 29, 12, 0))
+        );
+        assert_eq!(next_start(events, utc(2028, 3, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn excluded_and_overridden_occurrences() {
+        let events = "BEGIN:VEVENT\r\nUID:standup\r\nSUMMARY:Standup\r\n\
+                      DTSTART:20240601T100000Z\r\nRRULE:FREQ=DAILY\r\n\
+                      EXDATE:20240603T100000Z\r\nEND:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:standup\r\nSUMMARY:Standup (moved)\r\n\
+                      RECURRENCE-ID:20240604T100000Z\r\nDTSTART:20240604T150000Z\r\n\
+                      END:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:standup\r\nSUMMARY:Standup\r\nSTATUS:CANCELLED\r\n\
+                      RECURRENCE-ID:20240605T100000Z\r\nDTSTART:20240605T100000Z\r\n\
+                      END:VEVENT\r\n";
+        assert_eq!(
+            next(events, &filter(None, None), utc(2024, 6, 2, 12, 0)),
+            Some((String::from("Standup (moved)"), utc(2024, 6, 4, 15, 0)))
+        );
+        assert_eq!(
+            next_start(events, utc(2024, 6, 4, 15, 0)),
+            Some(utc(2024, 6, 6, 10, 0))
+        );
+    }
+
+    #[test]
+    fn filters_by_category_and_summary() {
+        let events = "BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Team lunch\r\nCATEGORIES:Social\r\n\
+                      DTSTART:20240510T120000Z\r\nEND:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:2\r\nSUMMARY:Essay deadline\r\n\
+                      CATEGORIES:Work,Deadlines\r\nDTSTART:20240520T120000Z\r\nEND:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:3\r\nSUMMARY:Thesis deadline\r\n\
+                      CATEGORIES:DEADLINES\r\nDTSTART:20240620T120000Z\r\nEND:VEVENT\r\n";
+        let now = utc(2024, 5, 1, 0, 0);
+        assert_eq!(
+            next(events, &filter(Some("deadlines"), None), now)
+                .unwrap()
+                .0,
+            "Essay deadline"
+        );
+        assert_eq!(
+            next(events, &filter(None, Some("^Thesis")), now).unwrap().0,
+            "Thesis deadline"
+        );
+        assert_eq!(
+            next(events, &filter(Some("social"), Some("deadline")), now),
+            None
+        );
+    }
+
+    #[test]
+    fn unsupported_rules_are_skipped() {
+        let events = "BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Ping\r\nDTSTART:20240601T100000Z\r\n\
+                      RRULE:FREQ=HOURLY\r\nEND:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:2\r\nSUMMARY:Standup\r\nDTSTART:20240603T090000Z\r\n\
+                      RRULE:FREQ=MONTHLY;BYSETPOS=-1;BYDAY=MO\r\nEND:VEVENT\r\n\
+                      BEGIN:VEVENT\r\nUID:3\r\nSUMMARY:Launch\r\nDTSTART:20240610T090000Z\r\n\
+                      END:VEVENT\r\n";
+        let now = utc(2024, 6, 1, 0, 0);
+        assert_eq!(
+            next(events, &filter(None, None), now),
+            Some((String::from("Launch"), utc(2024, 6, 10, 9, 0)))
+        );
+        // The only matching event is skipped, so there is no next event
+        assert_eq!(next(events, &filter(None, Some("Ping")), now), None);
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{bail, Context};
+use image::Rgba;
+
+use crate::config::ConfigRampStop;
+
+fn srgb_to_linear(c: u8) -> f64 {
+    let c = c as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f64) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Converts sRGB to OKLab (https://bottosson.github.io/posts/oklab/).
+fn to_oklab(colour: [u8; 3]) -> [f64; 3] {
+    let [r, g, b] = colour.map(srgb_to_linear);
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+/// Converts OKLab to sRGB, clamping colours outside the sRGB gamut.
+fn from_oklab([lightness, a, b]: [f64; 3]) -> [u8; 3] {
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+    .map(linear_to_srgb)
+}
+
+/// The colour a fraction `t` of the way from `from` to `to`, interpolated in OKLab so that the
+/// change looks even.
+pub(crate) fn interpolate_oklab(from: [u8; 3], to: [u8; 3], t: f64) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+    if t == 0.0 {
+        return from;
+    }
+    if t == 1.0 {
+        return to;
+    }
+    let (from, to) = (to_oklab(from), to_oklab(to));
+    from_oklab([0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * t))
+}
+
+/// Colours at positions from 0 (the start of a countdown) to 1 (its end).
+#[derive(Debug, Clone)]
+pub(crate) struct ColourRamp {
+    /// Sorted by position
+    stops: Vec<(f64, [u8; 3])>,
+}
+
+impl ColourRamp {
+    /// Resolves the position of each stop, which is either one of the named `phases` (with their
+    /// positions), or a percentage such as `'25%'`.
+    pub(crate) fn new(stops: &[ConfigRampStop], phases: &[(&str, f64)]) -> anyhow::Result<Self> {
+        if stops.is_empty() {
+            bail!("A colour ramp needs at least one stop");
+        }
+        let mut resolved = Vec::with_capacity(stops.len());
+        for stop in stops {
+            let position = match stop.at.trim().strip_suffix('%') {
+                Some(percentage) => {
+                    let percentage: f64 = percentage
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|percentage: &f64| percentage.is_finite())
+                        .with_context(|| format!("Invalid percentage {}", stop.at))?;
+                    (percentage / 100.0).clamp(0.0, 1.0)
+                }
+                None => match phases.iter().find(|(name, _)| *name == stop.at.trim()) {
+                    Some((_, position)) => position.clamp(0.0, 1.0),
+                    None => bail!(
+                        "Unknown phase {}; expected a percentage or one of {}",
+                        stop.at,
+                        phases
+                            .iter()
+                            .map(|(name, _)| *name)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                },
+            };
+            resolved.push((position, stop.colour));
+        }
+        resolved.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Ok(ColourRamp { stops: resolved })
+    }
+
+    /// The colour at `position`, which is clamped to the first and last stops.
+    pub(crate) fn colour_at(&self, position: f64) -> Rgba<u8> {
+        let position = if position.is_nan() { 0.0 } else { position };
+        let (first_position, first_colour) = self.stops[0];
+        let (last_position, last_colour) = self.stops[self.stops.len() - 1];
+        let [r, g, b] = if position <= first_position {
+            first_colour
+        } else if position >= last_position {
+            last_colour
+        } else {
+            // Exists (and isn't the first stop), as position is strictly between the two ends
+            let next = self
+                .stops
+                .iter()
+                .position(|(stop_position, _)| *stop_position > position)
+                .unwrap();
+            let (from_position, from) = self.stops[next - 1];
+            let (to_position, to) = self.stops[next];
+            interpolate_oklab(
+                from,
+                to,
+                (position - from_position) / (to_position - from_position),
+            )
+        };
+        Rgba([r, g, b, 255])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(at: &str, colour: [u8; 3]) -> ConfigRampStop {
+        ConfigRampStop {
+            at: String::from(at),
+            colour,
+        }
+    }
+
+    #[test]
+    fn oklab_round_trips() {
+        for colour in [
+            [0, 0, 0],
+            [255, 255, 255],
+            [255, 0, 0],
+            [255, 255, 0],
+            [12, 200, 99],
+        ] {
+            assert_eq!(from_oklab(to_oklab(colour)), colour);
+        }
+    }
+
+    #[test]
+    fn interpolation_is_perceptual() {
+        // Halfway in lightness is much darker than halfway in sRGB
+        assert_eq!(interpolate_oklab([0; 3], [255; 3], 0.5), [99; 3]);
+    }
+
+    #[test]
+    fn ramp_resolves_phases_and_percentages() {
+        let ramp = ColourRamp::new(
+            &[
+                stop("end", [0, 0, 0]),
+                stop("start", [255, 0, 0]),
+                stop("50%", [0, 0, 255]),
+            ],
+            &[("start", 0.0), ("end", 1.0)],
+        )
+        .unwrap();
+        assert_eq!(ramp.colour_at(0.0), Rgba([255, 0, 0, 255]));
+        assert_eq!(ramp.colour_at(0.5), Rgba([0, 0, 255, 255]));
+        assert_eq!(ramp.colour_at(1.0), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn ramp_clamps_out_of_range_positions() {
+        let ramp =
+            ColourRamp::new(&[stop("25%", [255; 3]), stop("75%", [255, 0, 0])], &[]).unwrap();
+        assert_eq!(ramp.colour_at(-3.0), Rgba([255; 4]));
+        assert_eq!(ramp.colour_at(0.1), Rgba([255; 4]));
+        assert_eq!(ramp.colour_at(0.9), Rgba([255, 0, 0, 255]));
+        assert_eq!(ramp.colour_at(42.0), Rgba([255, 0, 0, 255]));
+        assert_eq!(ramp.colour_at(f64::NAN), Rgba([255; 4]));
+    }
+
+    #[test]
+    fn ramp_rejects_unknown_phases() {
+        assert!(ColourRamp::new(&[stop("midway", [0; 3])], &[("start", 0.0)]).is_err());
+        assert!(ColourRamp::new(&[stop("x%", [0; 3])], &[]).is_err());
+        assert!(ColourRamp::new(&[], &[]).is_err());
+    }
+}
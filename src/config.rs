@@ -1,28 +1,165 @@
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
+use chrono::{NaiveTime, Weekday};
+use regex::Regex;
 use serde::Deserialize;
 use toml::value::Datetime;
 
-pub(crate) const TOML_TEMPLATE: &'static str = "\
+use crate::{
+    colour::ColourRamp,
+    countdown::{EVENT_RAMP_PHASES, TERM_RAMP_PHASES},
+    dateutils::toml_to_chrono,
+    imageops::{Screen, TEXT_MARGIN_PX},
+    render::{Monitor, DEFAULT_MONITOR_NAME},
+    scan::DECODABLE_EXTENSIONS,
+    template::{Template, TemplateKind},
+};
+
+pub(crate) const TOML_TEMPLATE: &str = "\
     [general]\n\
     # ttf_font_path = '/path/to/font.ttf'\n\
+    # screen_width_px = 1920\n\
+    # screen_height_px = 1080\n\
     \n\
     # [countdown]\n\
     # term_start = <YYYY-MM-DD>\n\
     # term_last_lecture = <YYYY-MM-DD>\n\
     # first_paper = <YYYY-MM-DD>\n\
     # last_paper_end_time = <YYYY-MM-DD>T<HH:MM:SS>\n\
+    # monitor = '<NAME>'\n\
+    # counting = 'calendar'  # or 'working'\n\
+    # style = 'number'  # or 'progress_bar' or 'calendar'\n\
+    # exam_granularity = 'days'  # or 'hours' or 'minutes'\n\
+    # timetable_rows = 5\n\
+    # [[countdown.papers]]\n\
+    # name = '<NAME>'\n\
+    # start = <YYYY-MM-DD>T<HH:MM:SS>\n\
+    # end = <YYYY-MM-DD>T<HH:MM:SS>\n\
+    # location = '<LOCATION>'\n\
+    # [[countdown.ramp]]\n\
+    # at = 'term_start'  # or 'mid_term', 'term_last_lecture', 'first_paper', or e.g. '50%'\n\
+    # colour = [255, 255, 255]\n\
+    # [countdown.format]\n\
+    # before_term = 'S'\n\
+    # term = '{days}'\n\
+    # exams = 'D{exam_day}'\n\
+    # after_exams = 'E'\n\
+    # caption = 'Calculated on {now:%a %-d %b} (<{hours}h left)'\n\
+    # week = 'Week {week} of {teaching_weeks}'\n\
+    # break_week = 'Break after week {week}'\n\
+    # [countdown.weeks]\n\
+    # start_day = 'mon'\n\
+    # [[countdown.weeks.breaks]]\n\
+    # start = <YYYY-MM-DD>\n\
+    # end = <YYYY-MM-DD>\n\
+    \n\
+    # [countdowns]\n\
+    # corner = 'bottom_left'  # or 'bottom_right', 'top_left', 'top_right'\n\
+    # monitor = '<NAME>'\n\
+    # [[countdowns.events]]\n\
+    # label = '<LABEL>'\n\
+    # target = <YYYY-MM-DD>T<HH:MM:SS>\n\
+    # start = <YYYY-MM-DD>\n\
+    # counting = 'calendar'  # or 'working'\n\
+    # style = 'number'  # or 'progress_bar' or 'calendar'\n\
+    # [[countdowns.calendars]]\n\
+    # path = '/path/to/calendar.ics'\n\
+    # label = '<LABEL>'\n\
+    # category = '<CATEGORY>'\n\
+    # summary = '<REGEX>'\n\
+    # ramp_days = 14\n\
+    # counting = 'calendar'  # or 'working'\n\
+    # style = 'number'  # or 'progress_bar' or 'calendar'\n\
+    # [[countdowns.ramp]]\n\
+    # at = 'start'  # or 'target', or e.g. '50%'\n\
+    # colour = [255, 255, 255]\n\
+    # [countdowns.format]\n\
+    # value = '{days}'\n\
+    # caption = '{event} on {target:%a %-d %b} (<{hours}h left)'\n\
+    \n\
+    # [working_days]\n\
+    # weekend = ['sat', 'sun']\n\
+    # holidays = [<YYYY-MM-DD>]\n\
+    # holiday_file = '/path/to/holidays.txt'\n\
     \n\
     # [overlay]\n\
     # text = ''\n\
+    # monitor = '<NAME>'\n\
+    \n\
+    # [selection]\n\
+    # mode = 'uniform'  # or 'shuffle_bag' or 'weighted'\n\
+    # no_repeat_within = 7\n\
+    # move_quarantined = false\n\
+    \n\
+    # [scan]\n\
+    # recursive = true\n\
+    # include = ['**/*']\n\
+    # exclude = ['Drafts/**']\n\
+    # extensions = ['jpg', 'jpeg', 'png', 'webp']\n\
+    \n\
+    # [crop]\n\
+    # strategy = 'centre'  # or 'edge_density' or 'entropy'\n\
+    \n\
+    # [fit]\n\
+    # mode = 'cover'  # or 'contain_blur', 'letterbox', 'tile', 'centre', 'stretch'\n\
+    # fill_colour = [0, 0, 0]\n\
+    # [[fit.overrides]]\n\
+    # pattern = 'Portraits/**'\n\
+    # mode = 'contain_blur'\n\
+    \n\
+    # [layout]\n\
+    # top = 20\n\
+    # bottom = 40\n\
+    # left = 0\n\
+    # right = 0\n\
+    \n\
+    # [setter]\n\
+    # backend = 'wallpaper_crate'  # or 'swaybg', 'swww', 'feh', 'hyprpaper', 'command'\n\
+    # command = 'feh --bg-fill {path}'  # split on whitespace, no quoting; don't quote {path}\n\
+    # refresh_with_blank = true\n\
+    \n\
+    # [schedule]\n\
+    # daily = ['00:00']\n\
+    # every_hours = 6\n\
+    # phase_boundaries = true\n\
+    \n\
+    # [display]\n\
+    # output = 'spanned'  # or 'per_monitor'\n\
+    # panorama = false\n\
+    # [[display.monitors]]\n\
+    # name = '<NAME>'\n\
+    # width_px = 1920\n\
+    # height_px = 1080\n\
+    # x_px = 0\n\
+    # y_px = 0\n\
 ";
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct Config {
     pub(crate) general: ConfigGeneral,
     pub(crate) countdown: Option<ConfigCountdown>,
+    #[serde(default)]
+    pub(crate) countdowns: ConfigCountdowns,
+    #[serde(default)]
+    pub(crate) working_days: ConfigWorkingDays,
     pub(crate) overlay: Option<ConfigOverlay>,
+    #[serde(default)]
+    pub(crate) selection: ConfigSelection,
+    #[serde(default)]
+    pub(crate) scan: ConfigScan,
+    #[serde(default)]
+    pub(crate) crop: ConfigCrop,
+    #[serde(default)]
+    pub(crate) fit: ConfigFit,
+    #[serde(default)]
+    pub(crate) layout: ConfigLayout,
+    #[serde(default)]
+    pub(crate) display: ConfigDisplay,
+    #[serde(default)]
+    pub(crate) setter: ConfigSetter,
+    #[serde(default)]
+    pub(crate) schedule: ConfigSchedule,
 }
 
 impl Config {
@@ -34,21 +171,264 @@ impl Config {
         let toml_str = std::fs::read_to_string(&config_toml_path).with_context(|| {
             format!(
                 "Failed to read configuration from {}",
-                config_toml_path.to_string_lossy().to_string()
+                config_toml_path.to_string_lossy()
             )
         })?;
-        Ok(toml::from_str(&toml_str).with_context(|| {
+        let config: Self = toml::from_str(&toml_str).with_context(|| {
             format!(
                 "Please fix the TOML file at {}. (Is this the first time this program is run?)",
-                config_toml_path.to_string_lossy().to_string()
+                config_toml_path.to_string_lossy()
             )
-        })?)
+        })?;
+        config.validate().with_context(|| {
+            format!(
+                "Please fix the TOML file at {}",
+                config_toml_path.to_string_lossy()
+            )
+        })?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.general.screen_width_px == 0 || self.general.screen_height_px == 0 {
+            bail!("screen_width_px and screen_height_px must be non-zero");
+        }
+        for monitor in self.monitors() {
+            let layout = monitor.layout;
+            // The text also keeps a margin from the insets
+            let margin = monitor.screen.scale_px(TEXT_MARGIN_PX) as u32;
+            if layout
+                .left
+                .checked_add(layout.right)
+                .and_then(|insets| insets.checked_add(margin))
+                .is_none_or(|insets| insets >= monitor.screen.width_px)
+                || layout
+                    .top
+                    .checked_add(layout.bottom)
+                    .and_then(|insets| insets.checked_add(margin))
+                    .is_none_or(|insets| insets >= monitor.screen.height_px)
+            {
+                bail!(
+                    "Insets of monitor {} leave no space to draw in (the text is also kept {} px \
+                     from them)",
+                    monitor.name,
+                    margin
+                );
+            }
+        }
+        let mut names = HashSet::new();
+        for monitor in &self.display.monitors {
+            if monitor.width_px == 0 || monitor.height_px == 0 {
+                bail!(
+                    "width_px and height_px of monitor {} must be non-zero",
+                    monitor.name
+                );
+            }
+            if !names.insert(monitor.name.as_str()) {
+                bail!("There is more than one monitor named {}", monitor.name);
+            }
+            // The name is part of the file name of per-monitor output
+            if monitor.name.is_empty()
+                || monitor
+                    .name
+                    .chars()
+                    .any(|c| c == '/' || c == '\\' || c.is_control())
+            {
+                bail!(
+                    "Monitor name {:?} must be non-empty, without slashes or control characters",
+                    monitor.name
+                );
+            }
+            if monitor.x_px.checked_add(monitor.width_px).is_none()
+                || monitor.y_px.checked_add(monitor.height_px).is_none()
+            {
+                bail!("Monitor {} is too far from the origin", monitor.name);
+            }
+        }
+        if self.setter.backend == SetterBackend::Command
+            && self
+                .setter
+                .command
+                .as_deref()
+                .is_none_or(|command| command.trim().is_empty())
+        {
+            bail!("[setter] command must be specified when backend = 'command'");
+        }
+        for event in &self.countdowns.events {
+            toml_to_chrono(&event.target)
+                .with_context(|| format!("Invalid target of countdown {}", event.label))?;
+            if let Some(start) = &event.start {
+                toml_to_chrono(start)
+                    .with_context(|| format!("Invalid start of countdown {}", event.label))?;
+            } else if event.style == CountdownStyle::ProgressBar {
+                bail!(
+                    "Countdown {} needs a start to be drawn as a progress_bar",
+                    event.label
+                );
+            }
+        }
+        if let Some(countdown) = &self.countdown {
+            ColourRamp::new(&countdown.ramp, &TERM_RAMP_PHASES.map(|phase| (phase, 0.0)))
+                .context("Invalid [countdown] ramp")?;
+            for paper in &countdown.papers {
+                let start = toml_to_chrono(&paper.start)
+                    .with_context(|| format!("Invalid start of paper {}", paper.name))?;
+                if let Some(end) = &paper.end {
+                    let end = toml_to_chrono(end)
+                        .with_context(|| format!("Invalid end of paper {}", paper.name))?;
+                    if end < start {
+                        bail!("Paper {} ends before it starts", paper.name);
+                    }
+                }
+            }
+            for teaching_break in countdown.weeks.iter().flat_map(|weeks| &weeks.breaks) {
+                let start = toml_to_chrono(&teaching_break.start)
+                    .context("Invalid start of [[countdown.weeks.breaks]]")?;
+                let end = toml_to_chrono(&teaching_break.end)
+                    .context("Invalid end of [[countdown.weeks.breaks]]")?;
+                if end < start {
+                    bail!(
+                        "Break from {} to {} ends before it starts",
+                        teaching_break.start,
+                        teaching_break.end
+                    );
+                }
+            }
+            let format = &countdown.format;
+            for template in [
+                &format.before_term,
+                &format.term,
+                &format.exams,
+                &format.after_exams,
+                &format.caption,
+                &format.week,
+                &format.break_week,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                Template::parse(template, TemplateKind::Term)
+                    .context("Invalid [countdown.format]")?;
+            }
+        }
+        for template in [
+            &self.countdowns.format.value,
+            &self.countdowns.format.caption,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            Template::parse(template, TemplateKind::Event)
+                .context("Invalid [countdowns.format]")?;
+        }
+        ColourRamp::new(
+            &self.countdowns.ramp,
+            &EVENT_RAMP_PHASES.map(|phase| (phase, 0.0)),
+        )
+        .context("Invalid [countdowns] ramp")?;
+        for calendar in &self.countdowns.calendars {
+            if calendar.style == CountdownStyle::ProgressBar && calendar.ramp_days.is_none() {
+                bail!(
+                    "Calendar {} needs ramp_days to be drawn as a progress_bar",
+                    calendar.path
+                );
+            }
+            if let Some(summary) = &calendar.summary {
+                Regex::new(summary).with_context(|| {
+                    format!("Invalid summary regex of calendar {}", calendar.path)
+                })?;
+            }
+        }
+        for time in &self.schedule.daily {
+            NaiveTime::parse_from_str(time, "%H:%M").with_context(|| {
+                format!("Invalid time {} in [schedule] daily; expected HH:MM", time)
+            })?;
+        }
+        if self
+            .schedule
+            .every_hours
+            .is_some_and(|hours| !(1..=24).contains(&hours))
+        {
+            bail!("[schedule] every_hours must be from 1 to 24");
+        }
+        for holiday in &self.working_days.holidays {
+            if holiday.date.is_none() || holiday.time.is_some() {
+                bail!("Holiday {} in [working_days] must be a date", holiday);
+            }
+        }
+        let widget_monitors = [
+            self.countdown.as_ref().and_then(|c| c.monitor.as_ref()),
+            self.countdowns.monitor.as_ref(),
+            self.overlay.as_ref().and_then(|c| c.monitor.as_ref()),
+        ];
+        for name in widget_monitors.into_iter().flatten() {
+            if !self.monitors().iter().any(|monitor| &monitor.name == name) {
+                bail!("There is no monitor named {}", name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Name of the monitor a widget with the given `monitor` setting is drawn on.
+    pub(crate) fn widget_monitor_name(&self, widget_monitor: &Option<String>) -> String {
+        widget_monitor
+            .clone()
+            .unwrap_or_else(|| self.monitors()[0].name.clone())
+    }
+
+    /// The monitors declared in `[display]`, or a single monitor with the `[general]` resolution
+    /// if there are none.
+    pub(crate) fn monitors(&self) -> Vec<Monitor> {
+        if self.display.monitors.is_empty() {
+            return vec![Monitor {
+                name: String::from(DEFAULT_MONITOR_NAME),
+                screen: self.general.screen(),
+                x_px: 0,
+                y_px: 0,
+                layout: self.layout,
+            }];
+        }
+        self.display
+            .monitors
+            .iter()
+            .map(|monitor| Monitor {
+                name: monitor.name.clone(),
+                screen: Screen {
+                    width_px: monitor.width_px,
+                    height_px: monitor.height_px,
+                },
+                x_px: monitor.x_px,
+                y_px: monitor.y_px,
+                layout: monitor.layout.unwrap_or(self.layout),
+            })
+            .collect()
     }
 }
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct ConfigGeneral {
     pub(crate) ttf_font_path: String,
+    #[serde(default = "ConfigGeneral::default_screen_width_px")]
+    pub(crate) screen_width_px: u32,
+    #[serde(default = "ConfigGeneral::default_screen_height_px")]
+    pub(crate) screen_height_px: u32,
+}
+
+impl ConfigGeneral {
+    fn default_screen_width_px() -> u32 {
+        1920
+    }
+
+    fn default_screen_height_px() -> u32 {
+        1080
+    }
+
+    pub(crate) fn screen(&self) -> Screen {
+        Screen {
+            width_px: self.screen_width_px,
+            height_px: self.screen_height_px,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -57,9 +437,544 @@ pub(crate) struct ConfigCountdown {
     pub(crate) term_last_lecture: Datetime,
     pub(crate) first_paper: Datetime,
     pub(crate) last_paper_end_time: Datetime,
+    /// Name of the monitor to draw on (default: the first)
+    pub(crate) monitor: Option<String>,
+    /// Whether the days left until first_paper are calendar or working days
+    #[serde(default)]
+    pub(crate) counting: DayCounting,
+    /// Colour from term_start to first_paper
+    #[serde(default = "ConfigCountdown::default_ramp")]
+    pub(crate) ramp: Vec<ConfigRampStop>,
+    #[serde(default)]
+    pub(crate) format: ConfigTermFormat,
+    #[serde(default)]
+    pub(crate) style: CountdownStyle,
+    /// Listed in a timetable below the countdown, if any
+    #[serde(default)]
+    pub(crate) papers: Vec<ConfigPaper>,
+    /// Number of papers shown in the timetable
+    #[serde(default = "ConfigCountdown::default_timetable_rows")]
+    pub(crate) timetable_rows: usize,
+    /// Teaching weeks, shown as an extra line in the countdown if present
+    pub(crate) weeks: Option<ConfigWeeks>,
+    /// What the countdown counts in during the exams
+    #[serde(default)]
+    pub(crate) exam_granularity: Granularity,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Granularity {
+    /// The day of the exams, e.g. `D3`
+    #[default]
+    Days,
+    /// The hours left until last_paper_end_time, e.g. `52h`
+    Hours,
+    /// The hours and minutes left until last_paper_end_time, e.g. `51:07`
+    Minutes,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigWeeks {
+    /// Day each week starts on (default: the weekday of term_start)
+    pub(crate) start_day: Option<Weekday>,
+    /// Weeks that start during a break aren't counted
+    #[serde(default)]
+    pub(crate) breaks: Vec<ConfigBreak>,
+}
+
+/// Dates from `start` to `end`, inclusive.
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigBreak {
+    pub(crate) start: Datetime,
+    pub(crate) end: Datetime,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigPaper {
+    pub(crate) name: String,
+    pub(crate) start: Datetime,
+    /// When the paper is done (default: the end of its day)
+    pub(crate) end: Option<Datetime>,
+    pub(crate) location: Option<String>,
+}
+
+/// How a countdown is drawn above its caption.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CountdownStyle {
+    /// The days left, in large text
+    #[default]
+    Number,
+    /// A bar filling up from the start (term_start for `[countdown]`) to the target
+    /// (last_paper_end_time)
+    ProgressBar,
+    /// Month grids with today and the target day (the exam days) marked
+    Calendar,
+}
+
+/// Templates of the `[countdown]` value in each phase, and of its caption (default: the
+/// built-in format)
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ConfigTermFormat {
+    pub(crate) before_term: Option<String>,
+    pub(crate) term: Option<String>,
+    pub(crate) exams: Option<String>,
+    pub(crate) after_exams: Option<String>,
+    pub(crate) caption: Option<String>,
+    /// The `[countdown.weeks]` line in teaching weeks, and in breaks
+    pub(crate) week: Option<String>,
+    pub(crate) break_week: Option<String>,
+}
+
+impl ConfigCountdown {
+    fn default_timetable_rows() -> usize {
+        5
+    }
+
+    fn default_ramp() -> Vec<ConfigRampStop> {
+        vec![
+            ConfigRampStop::new("term_start", [255, 255, 255]),
+            ConfigRampStop::new("mid_term", [255, 255, 0]),
+            ConfigRampStop::new("term_last_lecture", [255, 0, 0]),
+            ConfigRampStop::new("first_paper", [0, 0, 0]),
+        ]
+    }
+}
+
+/// A colour on a countdown's ramp, at a named phase (e.g. `'term_start'`) or a percentage of the
+/// way through the countdown (e.g. `'50%'`).
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct ConfigRampStop {
+    pub(crate) at: String,
+    pub(crate) colour: [u8; 3],
+}
+
+impl ConfigRampStop {
+    fn new(at: &str, colour: [u8; 3]) -> Self {
+        Self {
+            at: String::from(at),
+            colour,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigCountdowns {
+    /// Corner the countdowns (including `[countdown]`) are stacked in
+    #[serde(default)]
+    pub(crate) corner: Corner,
+    /// Name of the monitor to draw on (default: the first)
+    pub(crate) monitor: Option<String>,
+    #[serde(default)]
+    pub(crate) events: Vec<ConfigCountdownEvent>,
+    #[serde(default)]
+    pub(crate) calendars: Vec<ConfigCountdownCalendar>,
+    /// Colour from the start of each countdown to its target
+    #[serde(default = "ConfigCountdowns::default_ramp")]
+    pub(crate) ramp: Vec<ConfigRampStop>,
+    #[serde(default)]
+    pub(crate) format: ConfigEventFormat,
+}
+
+/// Templates of each event's value and caption (default: the built-in format)
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ConfigEventFormat {
+    pub(crate) value: Option<String>,
+    pub(crate) caption: Option<String>,
+}
+
+impl ConfigCountdowns {
+    fn default_ramp() -> Vec<ConfigRampStop> {
+        vec![
+            ConfigRampStop::new("start", [255, 255, 255]),
+            ConfigRampStop::new("50%", [255, 255, 0]),
+            ConfigRampStop::new("target", [255, 0, 0]),
+        ]
+    }
+}
+
+impl Default for ConfigCountdowns {
+    fn default() -> Self {
+        Self {
+            corner: Corner::default(),
+            monitor: None,
+            events: Vec::new(),
+            calendars: Vec::new(),
+            ramp: Self::default_ramp(),
+            format: ConfigEventFormat::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigCountdownEvent {
+    pub(crate) label: String,
+    pub(crate) target: Datetime,
+    /// When the colour starts to ramp from white to red (default: the countdown stays white)
+    pub(crate) start: Option<Datetime>,
+    #[serde(default)]
+    pub(crate) counting: DayCounting,
+    /// `'progress_bar'` needs a start
+    #[serde(default)]
+    pub(crate) style: CountdownStyle,
+}
+
+/// Counts down to the next matching event in an iCalendar file.
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigCountdownCalendar {
+    pub(crate) path: String,
+    /// Label of the countdown (default: the event's summary)
+    pub(crate) label: Option<String>,
+    /// Only events with this category (case-insensitive) are counted down to
+    pub(crate) category: Option<String>,
+    /// Only events whose summary matches this regex are counted down to
+    pub(crate) summary: Option<String>,
+    /// How many days before the event the colour starts to ramp from white to red (default: the
+    /// countdown stays white)
+    pub(crate) ramp_days: Option<u32>,
+    #[serde(default)]
+    pub(crate) counting: DayCounting,
+    /// `'progress_bar'` needs ramp_days
+    #[serde(default)]
+    pub(crate) style: CountdownStyle,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DayCounting {
+    #[default]
+    Calendar,
+    /// Days that aren't in the `[working_days]` weekend or holidays
+    Working,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigWorkingDays {
+    #[serde(default = "ConfigWorkingDays::default_weekend")]
+    pub(crate) weekend: Vec<Weekday>,
+    #[serde(default)]
+    pub(crate) holidays: Vec<Datetime>,
+    /// File with one holiday, or range of holidays, per line
+    pub(crate) holiday_file: Option<String>,
+}
+
+impl ConfigWorkingDays {
+    fn default_weekend() -> Vec<Weekday> {
+        vec![Weekday::Sat, Weekday::Sun]
+    }
+}
+
+impl Default for ConfigWorkingDays {
+    fn default() -> Self {
+        Self {
+            weekend: Self::default_weekend(),
+            holidays: Vec::new(),
+            holiday_file: None,
+        }
+    }
+}
+
+/// When the `daemon` subcommand renders and sets the background.
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigSchedule {
+    /// Times of day, as `HH:MM`
+    #[serde(default = "ConfigSchedule::default_daily")]
+    pub(crate) daily: Vec<String>,
+    /// Also every this many hours since midnight
+    pub(crate) every_hours: Option<u32>,
+    /// Also whenever a countdown changes phase, e.g. at term_start, or when a paper ends
+    #[serde(default = "ConfigSchedule::default_phase_boundaries")]
+    pub(crate) phase_boundaries: bool,
+}
+
+impl ConfigSchedule {
+    fn default_daily() -> Vec<String> {
+        vec![String::from("00:00")]
+    }
+
+    fn default_phase_boundaries() -> bool {
+        true
+    }
+
+    /// The `daily` times, which have been validated.
+    pub(crate) fn daily_times(&self) -> Vec<NaiveTime> {
+        self.daily
+            .iter()
+            .filter_map(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+            .collect()
+    }
+}
+
+impl Default for ConfigSchedule {
+    fn default() -> Self {
+        Self {
+            daily: Self::default_daily(),
+            every_hours: None,
+            phase_boundaries: Self::default_phase_boundaries(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Corner {
+    #[default]
+    BottomLeft,
+    BottomRight,
+    TopLeft,
+    TopRight,
 }
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct ConfigOverlay {
     pub(crate) text: String,
+    /// Name of the monitor to draw on (default: the first)
+    pub(crate) monitor: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigSelection {
+    #[serde(default)]
+    pub(crate) mode: SelectionMode,
+    /// An image is not chosen again if it was one of the last `no_repeat_within` picks
+    #[serde(default = "ConfigSelection::default_no_repeat_within")]
+    pub(crate) no_repeat_within: usize,
+    /// Whether images that fail to decode are also moved into `Working/Quarantine`
+    #[serde(default)]
+    pub(crate) move_quarantined: bool,
+}
+
+impl ConfigSelection {
+    fn default_no_repeat_within() -> usize {
+        7
+    }
+}
+
+impl Default for ConfigSelection {
+    fn default() -> Self {
+        Self {
+            mode: SelectionMode::default(),
+            no_repeat_within: Self::default_no_repeat_within(),
+            move_quarantined: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SelectionMode {
+    /// Every eligible image is equally likely to be chosen
+    #[default]
+    Uniform,
+    /// Every image is shown once, in a random order, before any image repeats
+    ShuffleBag,
+    /// Eligible images are chosen with probability proportional to their weight in ratings.toml
+    Weighted,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigScan {
+    /// Whether subdirectories are searched for images too
+    #[serde(default = "ConfigScan::default_recursive")]
+    pub(crate) recursive: bool,
+    /// Glob patterns, matched against paths relative to the image directory. If non-empty, only
+    /// matching files are candidates.
+    #[serde(default)]
+    pub(crate) include: Vec<String>,
+    /// Glob patterns, matched against paths relative to the image directory. Matching files are
+    /// never candidates.
+    #[serde(default)]
+    pub(crate) exclude: Vec<String>,
+    /// Only files with one of these extensions (case-insensitive) are candidates
+    #[serde(default = "ConfigScan::default_extensions")]
+    pub(crate) extensions: Vec<String>,
+}
+
+impl ConfigScan {
+    fn default_recursive() -> bool {
+        true
+    }
+
+    fn default_extensions() -> Vec<String> {
+        DECODABLE_EXTENSIONS.iter().map(|e| e.to_string()).collect()
+    }
+}
+
+impl Default for ConfigScan {
+    fn default() -> Self {
+        Self {
+            recursive: Self::default_recursive(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            extensions: Self::default_extensions(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ConfigDisplay {
+    #[serde(default)]
+    pub(crate) output: DisplayOutput,
+    /// Whether one image is stretched across all monitors, instead of one image per monitor
+    #[serde(default)]
+    pub(crate) panorama: bool,
+    #[serde(default)]
+    pub(crate) monitors: Vec<ConfigMonitor>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DisplayOutput {
+    /// A single image covering all monitors, each drawn at its offset
+    #[default]
+    Spanned,
+    /// One image per monitor
+    PerMonitor,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConfigMonitor {
+    pub(crate) name: String,
+    pub(crate) width_px: u32,
+    pub(crate) height_px: u32,
+    /// Offset of the monitor's top-left corner within the spanned canvas
+    #[serde(default)]
+    pub(crate) x_px: u32,
+    #[serde(default)]
+    pub(crate) y_px: u32,
+    /// Overrides `[layout]` for this monitor
+    pub(crate) layout: Option<ConfigLayout>,
+}
+
+/// Safe-area insets in pixels: space at each edge of a monitor taken up by panels, docks, etc.
+/// Text is never placed there.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub(crate) struct ConfigLayout {
+    pub(crate) top: u32,
+    pub(crate) bottom: u32,
+    pub(crate) left: u32,
+    pub(crate) right: u32,
+}
+
+impl Default for ConfigLayout {
+    fn default() -> Self {
+        // Header bar (20 px) and Latte dock (40 px)
+        Self {
+            top: 20,
+            bottom: 40,
+            left: 0,
+            right: 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub(crate) struct ConfigCrop {
+    #[serde(default)]
+    pub(crate) strategy: CropStrategy,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CropStrategy {
+    /// Keep the centre of the image
+    #[default]
+    Centre,
+    /// Keep the window with the most edges (detail)
+    EdgeDensity,
+    /// Keep the window with the most varied brightness
+    Entropy,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub(crate) struct ConfigFit {
+    #[serde(default)]
+    pub(crate) mode: FitMode,
+    /// RGB colour of the space not covered by the image (letterbox and centre modes)
+    #[serde(default)]
+    pub(crate) fill_colour: [u8; 3],
+    /// Per-image or per-folder fit modes; the first matching pattern wins
+    #[serde(default)]
+    pub(crate) overrides: Vec<ConfigFitOverride>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub(crate) struct ConfigFitOverride {
+    /// Glob pattern, matched against paths relative to the image directory
+    pub(crate) pattern: String,
+    pub(crate) mode: FitMode,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FitMode {
+    /// Scale to cover the screen, then crop the excess (see `[crop]`)
+    #[default]
+    Cover,
+    /// Scale to fit inside the screen, on top of a blurred, darkened copy of the image
+    ContainBlur,
+    /// Scale to fit inside the screen, on a solid colour
+    Letterbox,
+    /// Repeat the unscaled image from the top-left corner
+    Tile,
+    /// Place the unscaled image at the centre, on a solid colour
+    Centre,
+    /// Scale to the screen, ignoring the aspect ratio
+    Stretch,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ConfigSetter {
+    #[serde(default)]
+    pub(crate) backend: SetterBackend,
+    /// Command template for the 'command' backend, with `{path}` and `{monitor}` placeholders
+    pub(crate) command: Option<String>,
+    /// Whether a blank image is set before the real one, to force the desktop to refresh
+    /// (default depends on the backend)
+    pub(crate) refresh_with_blank: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SetterBackend {
+    /// The `wallpaper` crate, which supports most desktop environments
+    #[default]
+    WallpaperCrate,
+    Swaybg,
+    Swww,
+    Feh,
+    Hyprpaper,
+    /// A user-defined command (see `command`)
+    Command,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(&format!("[general]\nttf_font_path = ''\n{}", toml)).unwrap()
+    }
+
+    #[test]
+    fn insets_leave_room_for_the_text_margin() {
+        // 1920x1080 is the reference resolution, so the margin is 24 px
+        let fits = config("[layout]\nleft = 1000\nright = 895\ntop = 0\nbottom = 1055\n");
+        assert!(fits.validate().is_ok());
+        for layout in [
+            "left = 1000\nright = 896\ntop = 0\nbottom = 0",
+            "left = 0\nright = 0\ntop = 1000\nbottom = 56",
+            "left = 4294967295\nright = 1",
+        ] {
+            assert!(
+                config(&format!("[layout]\n{}\n", layout))
+                    .validate()
+                    .is_err(),
+                "{}",
+                layout
+            );
+        }
+    }
 }
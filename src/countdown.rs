@@ -1,28 +1,404 @@
 use anyhow::Context;
-use chrono::{DateTime, Datelike, Local};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
 use divrem::DivCeil;
 use image::Rgba;
 use now::DateTimeNow;
 
-use crate::{config::ConfigCountdown, dateutils::toml_to_chrono};
+use crate::{
+    colour::ColourRamp,
+    config::{
+        ConfigCountdown, ConfigCountdownEvent, ConfigEventFormat, ConfigRampStop, ConfigWeeks,
+        CountdownStyle, DayCounting, Granularity,
+    },
+    dateutils::toml_to_chrono,
+    template::{Template, TemplateKind, TemplateValues},
+    widgets::{CalendarGrid, PaperState, ProgressBar, Timetable, TimetableRow, Widget},
+    workdays::WorkingDays,
+};
 
-pub(crate) fn generate_today_string(config: &ConfigCountdown) -> anyhow::Result<String> {
-    let now = Local::now();
+/// One countdown as drawn on the background: a large `value` above a small `caption`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CountdownBlock {
+    pub(crate) value: String,
+    pub(crate) caption: String,
+    pub(crate) fill_colour: Rgba<u8>,
+    pub(crate) stroke_colour: Rgba<u8>,
+    /// Drawn instead of the value, unless it is `Widget::Number`
+    pub(crate) widget: Widget,
+    /// An extra line between the value and the caption
+    pub(crate) subtitle: Option<String>,
+}
+
+/// Phases that `[[countdown.ramp]]` stops can be placed at.
+pub(crate) const TERM_RAMP_PHASES: [&str; 4] =
+    ["term_start", "mid_term", "term_last_lecture", "first_paper"];
+/// Phases that `[[countdowns.ramp]]` stops can be placed at.
+pub(crate) const EVENT_RAMP_PHASES: [&str; 2] = ["start", "target"];
+
+/// The colour ramp of `[[countdowns.events]]` and calendars, from their start to their target.
+pub(crate) fn event_ramp(stops: &[ConfigRampStop]) -> anyhow::Result<ColourRamp> {
+    ColourRamp::new(stops, &[("start", 0.0), ("target", 1.0)])
+}
+
+/// The `[countdown]` exam-term block.
+pub(crate) fn term_block(
+    config: &ConfigCountdown,
+    working_days: &WorkingDays,
+    now: DateTime<Local>,
+) -> anyhow::Result<CountdownBlock> {
+    Ok(CountdownBlock {
+        value: get_countdown_str(config, working_days, now)?,
+        caption: generate_today_string(config, working_days, now)?,
+        fill_colour: get_font_fill_colour(config, now)?,
+        stroke_colour: get_font_stroke_colour(config, now)?,
+        widget: term_widget(config, now)?,
+        subtitle: week_line(config, working_days, now)?,
+    })
+}
+
+/// The `[countdown.weeks]` line, e.g. `Week 5 of 8`, if the weeks are shown and `now` is in term.
+fn week_line(
+    config: &ConfigCountdown,
+    working_days: &WorkingDays,
+    now: DateTime<Local>,
+) -> anyhow::Result<Option<String>> {
+    let Some(weeks) = &config.weeks else {
+        return Ok(None);
+    };
+    let (week, _) = teaching_week(config, weeks, now.date_naive())?;
+    let template = match week {
+        TeachingWeek::Outside => return Ok(None),
+        TeachingWeek::Teaching(_) => config
+            .format
+            .week
+            .as_deref()
+            .unwrap_or("Week {week} of {teaching_weeks}"),
+        TeachingWeek::Break(_) => config
+            .format
+            .break_week
+            .as_deref()
+            .unwrap_or("Break after week {week}"),
+    };
+    let (_, values) = term_values(config, working_days, now)?;
+    Ok(Some(
+        Template::parse(template, TemplateKind::Term)
+            .context("Invalid [countdown.format] week")?
+            .render(&values),
+    ))
+}
+
+/// The timetable of the `[[countdown.papers]]`, showing the last paper done and those to come, or
+/// `None` if there are no papers.
+pub(crate) fn timetable_block(
+    config: &ConfigCountdown,
+    now: DateTime<Local>,
+) -> anyhow::Result<Option<CountdownBlock>> {
+    if config.papers.is_empty() || config.timetable_rows == 0 {
+        return Ok(None);
+    }
+    let mut papers = Vec::with_capacity(config.papers.len());
+    for paper in &config.papers {
+        let start = toml_to_chrono(&paper.start)
+            .with_context(|| format!("Failed to parse start of paper {}", paper.name))?;
+        let end = match &paper.end {
+            Some(end) => toml_to_chrono(end)
+                .with_context(|| format!("Failed to parse end of paper {}", paper.name))?,
+            None => start.end_of_day(),
+        };
+        papers.push((paper, start, end));
+    }
+    papers.sort_by_key(|(_, start, _)| *start);
+
+    let done = papers.iter().filter(|(_, _, end)| now >= *end).count();
+    let first_row = done
+        .saturating_sub(1)
+        .min(papers.len().saturating_sub(config.timetable_rows));
+    let has_locations = papers.iter().any(|(paper, _, _)| paper.location.is_some());
+    let rows = papers[first_row..]
+        .iter()
+        .take(config.timetable_rows)
+        .map(|(paper, start, end)| {
+            let mut cells = vec![
+                paper.name.clone(),
+                start.format("%a %-d %b").to_string(),
+                match paper.end {
+                    Some(_) => format!("{}–{}", start.format("%H:%M"), end.format("%H:%M")),
+                    None => start.format("%H:%M").to_string(),
+                },
+            ];
+            if has_locations {
+                cells.push(paper.location.clone().unwrap_or_default());
+            }
+            let state = if now >= *end {
+                PaperState::Done
+            } else if start.date_naive() == now.date_naive() {
+                PaperState::Today
+            } else {
+                PaperState::Upcoming
+            };
+            TimetableRow { cells, state }
+        })
+        .collect();
+    Ok(Some(CountdownBlock {
+        value: format!("{}/{}", done, papers.len()),
+        caption: format!("Exam timetable ({}/{} done)", done, papers.len()),
+        fill_colour: get_font_fill_colour(config, now)?,
+        stroke_colour: get_font_stroke_colour(config, now)?,
+        widget: Widget::Timetable(Timetable { rows }),
+        subtitle: None,
+    }))
+}
+
+/// The `[countdown]` widget: a progress bar from term_start to last_paper_end_time, with markers
+/// at term_last_lecture and first_paper, or a calendar with the exam days marked.
+fn term_widget(config: &ConfigCountdown, now: DateTime<Local>) -> anyhow::Result<Widget> {
+    let term_start = toml_to_chrono(&config.term_start)
+        .with_context(|| "Failed to parse term_start")?
+        .beginning_of_day();
+    let term_last_lecture = toml_to_chrono(&config.term_last_lecture)
+        .with_context(|| "Failed to parse term_last_lecture")?
+        .beginning_of_day();
+    let first_paper = toml_to_chrono(&config.first_paper)
+        .with_context(|| "Failed to parse first_paper")?
+        .beginning_of_day();
     let last_paper_end_time = toml_to_chrono(&config.last_paper_end_time)
         .with_context(|| "Failed to parse last_paper_end_time")?;
 
-    let hours_left = DivCeil::div_ceil((last_paper_end_time - now).num_seconds(), 3600);
-    Ok(format!(
-        "Calculated on {} {} {} (<{}h left)",
-        now.format("%a"),
-        now.day(),
-        now.format("%b"),
-        hours_left
-    ))
+    Ok(match config.style {
+        CountdownStyle::Number => Widget::Number,
+        CountdownStyle::ProgressBar => {
+            let length = (last_paper_end_time - term_start).num_seconds().max(1) as f64;
+            let position =
+                |datetime: DateTime<Local>| (datetime - term_start).num_seconds() as f64 / length;
+            Widget::ProgressBar(ProgressBar {
+                progress: position(now),
+                markers: vec![position(term_last_lecture), position(first_paper)],
+            })
+        }
+        CountdownStyle::Calendar => Widget::Calendar(CalendarGrid::new(
+            now.date_naive(),
+            first_paper
+                .date_naive()
+                .iter_days()
+                .take_while(|date| *date <= last_paper_end_time.date_naive())
+                .collect(),
+        )),
+    })
+}
+
+/// An event to count down to, from `[[countdowns.events]]` or a calendar.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CountdownEvent {
+    pub(crate) label: String,
+    pub(crate) target: DateTime<Local>,
+    /// When the colour starts to ramp from white to red
+    pub(crate) start: Option<DateTime<Local>>,
+    pub(crate) counting: DayCounting,
+    pub(crate) style: CountdownStyle,
+}
+
+impl CountdownEvent {
+    pub(crate) fn from_config(event: &ConfigCountdownEvent) -> anyhow::Result<Self> {
+        Ok(CountdownEvent {
+            label: event.label.clone(),
+            target: toml_to_chrono(&event.target)
+                .with_context(|| format!("Failed to parse target of countdown {}", event.label))?,
+            start: event
+                .start
+                .as_ref()
+                .map(toml_to_chrono)
+                .transpose()
+                .with_context(|| format!("Failed to parse start of countdown {}", event.label))?,
+            counting: event.counting,
+            style: event.style,
+        })
+    }
+}
+
+/// The block of an event, counting the days left until its target, or `None` once the target
+/// has passed.
+pub(crate) fn event_block(
+    event: &CountdownEvent,
+    ramp: &ColourRamp,
+    format: &ConfigEventFormat,
+    working_days: &WorkingDays,
+    now: DateTime<Local>,
+) -> anyhow::Result<Option<CountdownBlock>> {
+    let target = event.target;
+    if now >= target {
+        return Ok(None);
+    }
+    // Calendar days, so that a daylight saving change doesn't cost a day
+    let days_left = (target.date_naive() - now.date_naive()).num_days();
+
+    let (phase, fill_colour) = match event.start {
+        None => ("countdown", Rgba([255; 4])),
+        Some(start) if now < start => ("before_start", Rgba([0, 255, 0, 255])),
+        Some(start) => {
+            let length = (target.date_naive() - start.date_naive()).num_days().max(1);
+            (
+                "countdown",
+                ramp.colour_at(1.0 - days_left as f64 / length as f64),
+            )
+        }
+    };
+    let values = TemplateValues {
+        days: working_days.days_between(now.date_naive(), target.date_naive(), event.counting),
+        calendar_days: days_left,
+        hours: DivCeil::div_ceil((target - now).num_seconds(), 3600),
+        minutes: DivCeil::div_ceil((target - now).num_seconds(), 60),
+        phase,
+        event: &event.label,
+        exam_day: 0,
+        exam_days: 0,
+        week: 0,
+        teaching_weeks: 0,
+        now,
+        target,
+    };
+    let render = |template: &str| {
+        Template::parse(template, TemplateKind::Event)
+            .context("Invalid [countdowns.format]")
+            .map(|template| template.render(&values))
+    };
+    Ok(Some(CountdownBlock {
+        value: render(
+            format
+                .value
+                .as_deref()
+                .unwrap_or(default_days_template(event.counting)),
+        )?,
+        caption: render(
+            format
+                .caption
+                .as_deref()
+                .unwrap_or("{event} on {target:%a %-d %b} (<{hours}h left)"),
+        )?,
+        fill_colour,
+        stroke_colour: Rgba([0, 0, 0, 127]),
+        widget: match (event.style, event.start) {
+            (CountdownStyle::ProgressBar, Some(start)) => {
+                let length = (target - start).num_seconds().max(1) as f64;
+                Widget::ProgressBar(ProgressBar {
+                    progress: (now - start).num_seconds() as f64 / length,
+                    markers: Vec::new(),
+                })
+            }
+            (CountdownStyle::Calendar, _) => Widget::Calendar(CalendarGrid::new(
+                now.date_naive(),
+                vec![target.date_naive()],
+            )),
+            // Without a start, there is nothing to show progress from
+            (CountdownStyle::Number | CountdownStyle::ProgressBar, _) => Widget::Number,
+        },
+        subtitle: None,
+    }))
 }
 
-pub(crate) fn get_countdown_str(config: &ConfigCountdown) -> anyhow::Result<String> {
-    let now = Local::now();
+/// The template of the days left when none is configured, e.g. `12`, or `12 wd` for working days.
+fn default_days_template(counting: DayCounting) -> &'static str {
+    match counting {
+        DayCounting::Calendar => "{days}",
+        DayCounting::Working => "{days} wd",
+    }
+}
+
+/// Phase of the `[countdown]` exam term, as named in `{phase}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TermPhase {
+    BeforeTerm,
+    Term,
+    Exams,
+    AfterExams,
+}
+
+impl TermPhase {
+    fn name(self) -> &'static str {
+        match self {
+            TermPhase::BeforeTerm => "before_term",
+            TermPhase::Term => "term",
+            TermPhase::Exams => "exams",
+            TermPhase::AfterExams => "after_exams",
+        }
+    }
+}
+
+/// Where a day is in the teaching weeks of `[countdown.weeks]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TeachingWeek {
+    /// Before the first or after the last teaching week
+    Outside,
+    /// Teaching week number, from 1
+    Teaching(i64),
+    /// A break after this many teaching weeks
+    Break(i64),
+}
+
+impl TeachingWeek {
+    fn number(self) -> i64 {
+        match self {
+            TeachingWeek::Outside => 0,
+            TeachingWeek::Teaching(week) | TeachingWeek::Break(week) => week,
+        }
+    }
+}
+
+/// The teaching week of `today`, and the number of teaching weeks. Weeks run from the week
+/// containing term_start to the week containing term_last_lecture, and weeks that start during a
+/// break aren't counted.
+fn teaching_week(
+    config: &ConfigCountdown,
+    weeks: &ConfigWeeks,
+    today: NaiveDate,
+) -> anyhow::Result<(TeachingWeek, i64)> {
+    let term_start = toml_to_chrono(&config.term_start)
+        .with_context(|| "Failed to parse term_start")?
+        .date_naive();
+    let term_last_lecture = toml_to_chrono(&config.term_last_lecture)
+        .with_context(|| "Failed to parse term_last_lecture")?
+        .date_naive();
+    let mut breaks = Vec::with_capacity(weeks.breaks.len());
+    for teaching_break in &weeks.breaks {
+        breaks.push((
+            toml_to_chrono(&teaching_break.start)
+                .with_context(|| "Failed to parse start of break")?
+                .date_naive(),
+            toml_to_chrono(&teaching_break.end)
+                .with_context(|| "Failed to parse end of break")?
+                .date_naive(),
+        ));
+    }
+
+    let start_day = weeks.start_day.unwrap_or(term_start.weekday());
+    let first_week = term_start - Duration::days(term_start.weekday().days_since(start_day) as i64);
+    let mut week = TeachingWeek::Outside;
+    let mut teaching_weeks = 0;
+    for week_start in first_week
+        .iter_weeks()
+        .take_while(|week_start| *week_start <= term_last_lecture)
+    {
+        let is_break = breaks
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&week_start));
+        if !is_break {
+            teaching_weeks += 1;
+        }
+        if (week_start..week_start + Duration::days(7)).contains(&today) {
+            week = match is_break {
+                true => TeachingWeek::Break(teaching_weeks),
+                false => TeachingWeek::Teaching(teaching_weeks),
+            };
+        }
+    }
+    Ok((week, teaching_weeks))
+}
+
+/// The phase of the exam term at `now`, and the values of the `[countdown]` templates.
+fn term_values(
+    config: &ConfigCountdown,
+    working_days: &WorkingDays,
+    now: DateTime<Local>,
+) -> anyhow::Result<(TermPhase, TemplateValues<'static>)> {
     let start_of_today = now.beginning_of_day();
     let term_start = toml_to_chrono(&config.term_start)
         .with_context(|| "Failed to parse term_start")?
@@ -33,24 +409,105 @@ pub(crate) fn get_countdown_str(config: &ConfigCountdown) -> anyhow::Result<Stri
     let last_paper_end_time = toml_to_chrono(&config.last_paper_end_time)
         .with_context(|| "Failed to parse last_paper_end_time")?;
 
-    if start_of_today < term_start {
-        Ok(String::from("S"))
+    let phase = if start_of_today < term_start {
+        TermPhase::BeforeTerm
     } else if start_of_today < first_paper {
-        Ok((first_paper - start_of_today).num_days().to_string())
+        TermPhase::Term
+    } else if now < last_paper_end_time {
+        TermPhase::Exams
     } else {
-        if now < last_paper_end_time {
-            Ok(format!(
-                "D{}",
-                (start_of_today - first_paper).num_days() + 1
-            ))
-        } else {
-            Ok(String::from("E"))
+        TermPhase::AfterExams
+    };
+    // Days are counted to the first paper, then during the exams to the last
+    let target = match phase {
+        TermPhase::BeforeTerm | TermPhase::Term => first_paper,
+        TermPhase::Exams | TermPhase::AfterExams => last_paper_end_time,
+    };
+    let today = now.date_naive();
+    let (teaching_week, teaching_weeks) = match &config.weeks {
+        Some(weeks) => teaching_week(config, weeks, today)?,
+        None => (TeachingWeek::Outside, 0),
+    };
+    let days_left = |counting| {
+        working_days
+            .days_between(today, target.date_naive(), counting)
+            .max(0)
+    };
+    Ok((
+        phase,
+        TemplateValues {
+            days: days_left(config.counting),
+            calendar_days: days_left(DayCounting::Calendar),
+            hours: DivCeil::div_ceil((last_paper_end_time - now).num_seconds(), 3600),
+            minutes: DivCeil::div_ceil((last_paper_end_time - now).num_seconds(), 60),
+            phase: phase.name(),
+            event: "exams",
+            exam_day: match phase {
+                TermPhase::Exams => (today - first_paper.date_naive()).num_days() + 1,
+                _ => 0,
+            },
+            exam_days: (last_paper_end_time.date_naive() - first_paper.date_naive()).num_days() + 1,
+            week: teaching_week.number(),
+            teaching_weeks,
+            now,
+            target,
+        },
+    ))
+}
+
+pub(crate) fn generate_today_string(
+    config: &ConfigCountdown,
+    working_days: &WorkingDays,
+    now: DateTime<Local>,
+) -> anyhow::Result<String> {
+    let (phase, values) = term_values(config, working_days, now)?;
+    let default = match (phase, config.exam_granularity) {
+        (TermPhase::Exams, Granularity::Hours) => {
+            "Calculated on {now:%a %-d %b %H:%M} (<{hours}h left)"
         }
-    }
+        (TermPhase::Exams, Granularity::Minutes) => {
+            "Calculated on {now:%a %-d %b %H:%M} ({clock} left)"
+        }
+        _ => "Calculated on {now:%a %-d %b} (<{hours}h left)",
+    };
+    let template = config.format.caption.as_deref().unwrap_or(default);
+    Ok(Template::parse(template, TemplateKind::Term)
+        .context("Invalid [countdown.format] caption")?
+        .render(&values))
+}
+
+pub(crate) fn get_countdown_str(
+    config: &ConfigCountdown,
+    working_days: &WorkingDays,
+    now: DateTime<Local>,
+) -> anyhow::Result<String> {
+    let (phase, values) = term_values(config, working_days, now)?;
+    let format = &config.format;
+    let template = match phase {
+        TermPhase::BeforeTerm => format.before_term.as_deref().unwrap_or("S"),
+        TermPhase::Term => format
+            .term
+            .as_deref()
+            .unwrap_or(default_days_template(config.counting)),
+        TermPhase::Exams => format
+            .exams
+            .as_deref()
+            .unwrap_or(match config.exam_granularity {
+                Granularity::Days => "D{exam_day}",
+                Granularity::Hours => "{hours}h",
+                Granularity::Minutes => "{clock}",
+            }),
+        TermPhase::AfterExams => format.after_exams.as_deref().unwrap_or("E"),
+    };
+    Ok(Template::parse(template, TemplateKind::Term)
+        .with_context(|| format!("Invalid [countdown.format] {}", phase.name()))?
+        .render(&values))
 }
 
-pub(crate) fn get_font_fill_colour(config: &ConfigCountdown) -> anyhow::Result<Rgba<u8>> {
-    let now: DateTime<Local> = Local::now();
+pub(crate) fn get_font_fill_colour(
+    config: &ConfigCountdown,
+    now: DateTime<Local>,
+) -> anyhow::Result<Rgba<u8>> {
     let start_of_today = now.beginning_of_day();
     let term_start = toml_to_chrono(&config.term_start)
         .with_context(|| "Failed to parse term_start")?
@@ -71,53 +528,30 @@ pub(crate) fn get_font_fill_colour(config: &ConfigCountdown) -> anyhow::Result<R
         // During examination period
         Ok(Rgba([255; 4]))
     } else {
-        if start_of_today <= term_last_lecture {
-            // during term time; white -> orange -> red
-            let half_term_length =
-                DivCeil::div_ceil((term_last_lecture - term_start).num_days(), 2)
-                    .clamp(1, i64::MAX);
-            let term_remaining = (term_last_lecture - start_of_today).num_days();
-            if term_remaining > half_term_length {
-                Ok(Rgba([
-                    255,
-                    255,
-                    ((term_remaining - half_term_length) * 255 / half_term_length)
-                        .try_into()
-                        .with_context(|| {
-                            "Number of days left in countdown exceeds 255 [branch 1]"
-                        })?,
-                    255,
-                ]))
-            } else {
-                Ok(Rgba([
-                    255,
-                    (term_remaining * 255 / half_term_length)
-                        .try_into()
-                        .with_context(|| {
-                            "Number of days left in countdown exceeds 255 [branch 2]"
-                        })?,
-                    0,
-                    255,
-                ]))
-            }
-        } else {
-            // final sprint; red -> black
-            let final_dash_length = (first_paper - term_last_lecture).num_days();
-            let final_dash_remaining = (first_paper - start_of_today).num_days();
-            Ok(Rgba([
-                (final_dash_remaining * 255 / final_dash_length)
-                    .try_into()
-                    .with_context(|| "Number of days left in countdown exceeds 255 [branch 3]")?,
-                0,
-                0,
-                255,
-            ]))
-        }
+        // During term time and the final sprint, follow the ramp from term_start to first_paper
+        let days_since_term_start = |datetime: DateTime<Local>| {
+            (datetime.date_naive() - term_start.date_naive()).num_days() as f64
+        };
+        let ramp_length = days_since_term_start(first_paper).max(1.0);
+        let last_lecture = days_since_term_start(term_last_lecture) / ramp_length;
+        let ramp = ColourRamp::new(
+            &config.ramp,
+            &[
+                ("term_start", 0.0),
+                ("mid_term", last_lecture / 2.0),
+                ("term_last_lecture", last_lecture),
+                ("first_paper", 1.0),
+            ],
+        )
+        .context("Invalid [countdown] ramp")?;
+        Ok(ramp.colour_at(days_since_term_start(start_of_today) / ramp_length))
     }
 }
 
-pub(crate) fn get_font_stroke_colour(config: &ConfigCountdown) -> anyhow::Result<Rgba<u8>> {
-    let now: DateTime<Local> = Local::now();
+pub(crate) fn get_font_stroke_colour(
+    config: &ConfigCountdown,
+    now: DateTime<Local>,
+) -> anyhow::Result<Rgba<u8>> {
     let start_of_today = now.beginning_of_day();
     let term_start = toml_to_chrono(&config.term_start)
         .with_context(|| "Failed to parse term_start")?
@@ -147,3 +581,439 @@ pub(crate) fn get_font_stroke_colour(config: &ConfigCountdown) -> anyhow::Result
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ConfigCountdowns, ConfigWorkingDays},
+        dateutils::parse_local_datetime,
+    };
+
+    fn config() -> ConfigCountdown {
+        toml::from_str(
+            "term_start = 2024-04-15\n\
+             term_last_lecture = 2024-06-07\n\
+             first_paper = 2024-06-17\n\
+             last_paper_end_time = 2024-07-05T17:00:00\n",
+        )
+        .unwrap()
+    }
+
+    fn working_days() -> WorkingDays {
+        WorkingDays::from_config(&ConfigWorkingDays::default()).unwrap()
+    }
+
+    fn at(s: &str) -> DateTime<Local> {
+        parse_local_datetime(s).unwrap()
+    }
+
+    #[test]
+    fn today_string_shows_date_and_hours_left() {
+        assert_eq!(
+            generate_today_string(&config(), &working_days(), at("2024-07-05T09:30:00")).unwrap(),
+            "Calculated on Fri 5 Jul (<8h left)"
+        );
+    }
+
+    #[test]
+    fn countdown_str_before_term() {
+        assert_eq!(
+            get_countdown_str(&config(), &working_days(), at("2024-04-10")).unwrap(),
+            "S"
+        );
+    }
+
+    #[test]
+    fn countdown_str_counts_days_to_first_paper() {
+        assert_eq!(
+            get_countdown_str(&config(), &working_days(), at("2024-04-15T12:00:00")).unwrap(),
+            "63"
+        );
+        assert_eq!(
+            get_countdown_str(&config(), &working_days(), at("2024-06-12")).unwrap(),
+            "5"
+        );
+    }
+
+    #[test]
+    fn countdown_str_counts_working_days_to_first_paper() {
+        let mut config = config();
+        config.counting = DayCounting::Working;
+        let working_days =
+            WorkingDays::from_config(&toml::from_str("holidays = [2024-06-13]").unwrap()).unwrap();
+        // Wed 12 June to Mon 17 June, skipping the weekend and the holiday
+        assert_eq!(
+            get_countdown_str(&config, &working_days, at("2024-06-12")).unwrap(),
+            "2 wd"
+        );
+    }
+
+    #[test]
+    fn countdown_str_follows_phase_templates() {
+        let mut config = config();
+        config.format = toml::from_str(
+            "term = '{weeks} weeks to go'\n\
+             exams = 'Exam day {exam_day}/{exam_days}'\n\
+             caption = '{phase}: {event} end {target:%-d %b}'\n",
+        )
+        .unwrap();
+        assert_eq!(
+            get_countdown_str(&config, &working_days(), at("2024-05-20")).unwrap(),
+            "4 weeks to go"
+        );
+        assert_eq!(
+            get_countdown_str(&config, &working_days(), at("2024-06-19")).unwrap(),
+            "Exam day 3/19"
+        );
+        assert_eq!(
+            get_countdown_str(&config, &working_days(), at("2024-04-10")).unwrap(),
+            "S"
+        );
+        assert_eq!(
+            generate_today_string(&config, &working_days(), at("2024-06-19")).unwrap(),
+            "exams: exams end 5 Jul"
+        );
+    }
+
+    fn config_with_weeks(weeks: &str) -> ConfigCountdown {
+        let mut config = config();
+        config.weeks = Some(toml::from_str(weeks).unwrap());
+        config
+    }
+
+    fn week(config: &ConfigCountdown, now: &str) -> Option<String> {
+        week_line(config, &working_days(), at(now)).unwrap()
+    }
+
+    #[test]
+    fn no_week_line_without_weeks() {
+        assert!(week(&config(), "2024-05-15").is_none());
+    }
+
+    #[test]
+    fn week_line_skips_breaks() {
+        let config = config_with_weeks("[[breaks]]\nstart = 2024-05-13\nend = 2024-05-17\n");
+        assert_eq!(week(&config, "2024-04-15").unwrap(), "Week 1 of 7");
+        assert_eq!(week(&config, "2024-05-15").unwrap(), "Break after week 4");
+        assert_eq!(week(&config, "2024-05-20").unwrap(), "Week 5 of 7");
+        assert_eq!(week(&config, "2024-06-09").unwrap(), "Week 7 of 7");
+        assert!(week(&config, "2024-04-14").is_none());
+        assert!(week(&config, "2024-06-10").is_none());
+        assert!(week(&config_with_weeks(""), "2024-05-15")
+            .unwrap()
+            .starts_with("Week 5 of 8"));
+    }
+
+    #[test]
+    fn week_line_starts_on_configured_day() {
+        let mut config = config_with_weeks("start_day = 'thu'\n");
+        assert_eq!(week(&config, "2024-04-15").unwrap(), "Week 1 of 9");
+        assert_eq!(week(&config, "2024-04-18").unwrap(), "Week 2 of 9");
+        config.format.week = Some(String::from("{phase}: week {week}, {days} days left"));
+        assert_eq!(
+            week(&config, "2024-06-07").unwrap(),
+            "term: week 9, 10 days left"
+        );
+    }
+
+    #[test]
+    fn countdown_str_counts_exam_days() {
+        assert_eq!(
+            get_countdown_str(&config(), &working_days(), at("2024-06-17T09:00:00")).unwrap(),
+            "D1"
+        );
+        assert_eq!(
+            get_countdown_str(&config(), &working_days(), at("2024-07-05T16:00:00")).unwrap(),
+            "D19"
+        );
+    }
+
+    #[test]
+    fn exam_countdown_in_hours_and_minutes() {
+        let mut config = config();
+        let now = at("2024-07-03T14:53:20");
+        config.exam_granularity = Granularity::Hours;
+        assert_eq!(
+            get_countdown_str(&config, &working_days(), now).unwrap(),
+            "51h"
+        );
+        assert_eq!(
+            generate_today_string(&config, &working_days(), now).unwrap(),
+            "Calculated on Wed 3 Jul 14:53 (<51h left)"
+        );
+        config.exam_granularity = Granularity::Minutes;
+        assert_eq!(
+            get_countdown_str(&config, &working_days(), now).unwrap(),
+            "50:07"
+        );
+        assert_eq!(
+            generate_today_string(&config, &working_days(), now).unwrap(),
+            "Calculated on Wed 3 Jul 14:53 (50:07 left)"
+        );
+        // Only the exams are counted down more finely
+        assert_eq!(
+            get_countdown_str(&config, &working_days(), at("2024-06-12")).unwrap(),
+            "5"
+        );
+    }
+
+    #[test]
+    fn countdown_str_after_last_paper() {
+        assert_eq!(
+            get_countdown_str(&config(), &working_days(), at("2024-07-05T17:00:00")).unwrap(),
+            "E"
+        );
+    }
+
+    #[test]
+    fn colours_outside_term() {
+        for now in ["2024-04-10", "2024-07-05T17:00:00"] {
+            assert_eq!(
+                get_font_fill_colour(&config(), at(now)).unwrap(),
+                Rgba([0, 255, 0, 255])
+            );
+            assert_eq!(
+                get_font_stroke_colour(&config(), at(now)).unwrap(),
+                Rgba([0, 0, 0, 127])
+            );
+        }
+    }
+
+    #[test]
+    fn colours_during_term_go_white_to_orange_to_red() {
+        assert_eq!(
+            get_font_fill_colour(&config(), at("2024-04-15")).unwrap(),
+            Rgba([255; 4])
+        );
+        assert_eq!(
+            get_font_fill_colour(&config(), at("2024-05-20")).unwrap(),
+            Rgba([255, 195, 0, 255])
+        );
+        assert_eq!(
+            get_font_fill_colour(&config(), at("2024-06-07")).unwrap(),
+            Rgba([255, 0, 0, 255])
+        );
+        assert_eq!(
+            get_font_stroke_colour(&config(), at("2024-05-20")).unwrap(),
+            Rgba([0, 0, 0, 127])
+        );
+    }
+
+    #[test]
+    fn colours_during_final_sprint_go_red_to_black() {
+        assert_eq!(
+            get_font_fill_colour(&config(), at("2024-06-12")).unwrap(),
+            Rgba([99, 0, 0, 255])
+        );
+        assert_eq!(
+            get_font_stroke_colour(&config(), at("2024-06-12")).unwrap(),
+            Rgba([255; 4])
+        );
+    }
+
+    #[test]
+    fn colours_follow_configured_ramp() {
+        let mut config = config();
+        config.ramp = [
+            ("term_start", [0, 0, 255]),
+            ("50%", [0, 0, 255]),
+            ("first_paper", [255, 0, 255]),
+        ]
+        .map(|(at, colour)| ConfigRampStop {
+            at: String::from(at),
+            colour,
+        })
+        .to_vec();
+        assert_eq!(
+            get_font_fill_colour(&config, at("2024-05-01")).unwrap(),
+            Rgba([0, 0, 255, 255])
+        );
+        assert_eq!(
+            get_font_fill_colour(&config, at("2024-06-16")).unwrap(),
+            Rgba([248, 17, 255, 255])
+        );
+    }
+
+    #[test]
+    fn colours_clamp_when_dates_are_out_of_order() {
+        let config: ConfigCountdown = toml::from_str(
+            "term_start = 2024-04-15\n\
+             term_last_lecture = 2024-06-30\n\
+             first_paper = 2024-06-17\n\
+             last_paper_end_time = 2024-07-05T17:00:00\n",
+        )
+        .unwrap();
+        assert!(get_font_fill_colour(&config, at("2024-06-10")).is_ok());
+    }
+
+    #[test]
+    fn colours_during_exams() {
+        assert_eq!(
+            get_font_fill_colour(&config(), at("2024-06-20")).unwrap(),
+            Rgba([255; 4])
+        );
+        assert_eq!(
+            get_font_stroke_colour(&config(), at("2024-06-20")).unwrap(),
+            Rgba([255, 0, 0, 255])
+        );
+    }
+
+    fn config_with_papers() -> ConfigCountdown {
+        toml::from_str(
+            "term_start = 2024-04-15\n\
+             term_last_lecture = 2024-06-07\n\
+             first_paper = 2024-06-17\n\
+             last_paper_end_time = 2024-07-05T17:00:00\n\
+             timetable_rows = 3\n\
+             [[papers]]\nname = 'Paper 2'\nstart = 2024-06-19T09:30:00\n\
+             end = 2024-06-19T12:30:00\nlocation = 'Exam Hall'\n\
+             [[papers]]\nname = 'Paper 1'\nstart = 2024-06-17T14:00:00\n\
+             [[papers]]\nname = 'Paper 3'\nstart = 2024-06-24T09:30:00\n\
+             [[papers]]\nname = 'Paper 4'\nstart = 2024-07-05T14:00:00\n",
+        )
+        .unwrap()
+    }
+
+    fn timetable(now: &str) -> (String, Vec<(String, PaperState)>) {
+        let block = timetable_block(&config_with_papers(), at(now))
+            .unwrap()
+            .unwrap();
+        let Widget::Timetable(timetable) = block.widget else {
+            panic!("{:?} is not a timetable", block.widget);
+        };
+        let rows = timetable
+            .rows
+            .into_iter()
+            .map(|row| (row.cells.join(" | "), row.state))
+            .collect();
+        (block.caption, rows)
+    }
+
+    #[test]
+    fn timetable_lists_papers_in_order() {
+        let (caption, rows) = timetable("2024-06-10");
+        assert_eq!(caption, "Exam timetable (0/4 done)");
+        assert_eq!(
+            rows,
+            [
+                ("Paper 1 | Mon 17 Jun | 14:00 | ", PaperState::Upcoming),
+                (
+                    "Paper 2 | Wed 19 Jun | 09:30–12:30 | Exam Hall",
+                    PaperState::Upcoming
+                ),
+                ("Paper 3 | Mon 24 Jun | 09:30 | ", PaperState::Upcoming),
+            ]
+            .map(|(row, state)| (String::from(row), state))
+        );
+    }
+
+    #[test]
+    fn timetable_dims_done_papers_and_highlights_today() {
+        let (caption, rows) = timetable("2024-06-19T11:00:00");
+        assert_eq!(caption, "Exam timetable (1/4 done)");
+        assert_eq!(
+            rows.iter().map(|(_, state)| *state).collect::<Vec<_>>(),
+            [PaperState::Done, PaperState::Today, PaperState::Upcoming]
+        );
+        // Near the end, the last rows are kept filled with papers, even if done
+        let (_, rows) = timetable("2024-06-25");
+        assert!(rows[0].0.starts_with("Paper 2"));
+        assert_eq!(rows[2].1, PaperState::Upcoming);
+        let (_, rows) = timetable("2024-07-06");
+        assert!(rows[0].0.starts_with("Paper 2"));
+        assert!(rows.iter().all(|(_, state)| *state == PaperState::Done));
+    }
+
+    #[test]
+    fn no_timetable_without_papers() {
+        assert!(timetable_block(&config(), at("2024-06-19"))
+            .unwrap()
+            .is_none());
+    }
+
+    fn ramp() -> ColourRamp {
+        event_ramp(&ConfigCountdowns::default().ramp).unwrap()
+    }
+
+    fn event(start: Option<&str>) -> CountdownEvent {
+        CountdownEvent::from_config(&ConfigCountdownEvent {
+            label: String::from("Launch"),
+            target: "2024-06-21T12:00:00".parse().unwrap(),
+            start: start.map(|start| start.parse().unwrap()),
+            counting: DayCounting::Calendar,
+            style: CountdownStyle::Number,
+        })
+        .unwrap()
+    }
+
+    fn block(event: &CountdownEvent, now: &str) -> Option<CountdownBlock> {
+        event_block(
+            event,
+            &ramp(),
+            &ConfigEventFormat::default(),
+            &working_days(),
+            at(now),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn event_block_counts_calendar_days_and_hours() {
+        let block = block(&event(None), "2024-06-20T23:30:00").unwrap();
+        assert_eq!(block.value, "1");
+        assert_eq!(block.caption, "Launch on Fri 21 Jun (<13h left)");
+        assert_eq!(block.fill_colour, Rgba([255; 4]));
+    }
+
+    #[test]
+    fn event_block_counts_working_days() {
+        let mut event = event(None);
+        event.counting = DayCounting::Working;
+        let block = block(&event, "2024-06-14T09:00:00").unwrap();
+        assert_eq!(block.value, "5 wd");
+        assert_eq!(block.caption, "Launch on Fri 21 Jun (<171h left)");
+    }
+
+    #[test]
+    fn event_block_follows_templates() {
+        let format: ConfigEventFormat = toml::from_str(
+            "value = '{event} in {days} days'\n\
+             caption = '{weeks} weeks, {phase}, {target:%H:%M}'\n",
+        )
+        .unwrap();
+        let block = event_block(
+            &event(Some("2024-06-01")),
+            &ramp(),
+            &format,
+            &working_days(),
+            at("2024-05-31T09:00:00"),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(block.value, "Launch in 21 days");
+        assert_eq!(block.caption, "3 weeks, before_start, 12:00");
+    }
+
+    #[test]
+    fn event_block_disappears_once_target_passes() {
+        assert!(block(&event(None), "2024-06-21T12:00:00").is_none());
+    }
+
+    #[test]
+    fn event_block_colour_ramps_from_start() {
+        let event = event(Some("2024-06-01"));
+        for (now, colour) in [
+            ("2024-05-31", [0, 255, 0]),
+            ("2024-06-01", [255, 255, 255]),
+            ("2024-06-11", [255, 255, 0]),
+            ("2024-06-21", [255, 0, 0]),
+        ] {
+            let [r, g, b] = colour;
+            assert_eq!(
+                block(&event, now).unwrap().fill_colour,
+                Rgba([r, g, b, 255])
+            );
+        }
+    }
+}
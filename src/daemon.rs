@@ -0,0 +1,261 @@
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Days, Local, NaiveTime, TimeZone};
+use now::DateTimeNow;
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use crate::{
+    calendar::next_calendar_event, config::Config, dateutils::toml_to_chrono, refresh::Refresher,
+};
+
+/// Longest wait between looking at the clock. Waits don't count time spent suspended, so this
+/// bounds how late a run missed during a suspend is caught up on.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Times of day of the `daily` and `every_hours` runs.
+fn times_of_day(config: &Config) -> Vec<NaiveTime> {
+    let mut times = config.schedule.daily_times();
+    if let Some(hours) = config.schedule.every_hours {
+        times.extend(
+            (0..24)
+                .step_by(hours as usize)
+                .filter_map(|hour| NaiveTime::from_hms_opt(hour, 0, 0)),
+        );
+    }
+    times
+}
+
+/// Instants at which a countdown changes phase, e.g. term_start or the end of a paper, so that
+/// the background looks different from just before.
+fn phase_boundaries(config: &Config, after: DateTime<Local>) -> Vec<DateTime<Local>> {
+    let mut boundaries = Vec::new();
+    if let Some(countdown) = &config.countdown {
+        for date in [
+            &countdown.term_start,
+            &countdown.term_last_lecture,
+            &countdown.first_paper,
+        ] {
+            boundaries.extend(
+                toml_to_chrono(date)
+                    .ok()
+                    .map(|date| date.beginning_of_day()),
+            );
+        }
+        boundaries.extend(toml_to_chrono(&countdown.last_paper_end_time).ok());
+        for paper in &countdown.papers {
+            let Ok(start) = toml_to_chrono(&paper.start) else {
+                continue;
+            };
+            boundaries.push(start);
+            boundaries.push(match &paper.end {
+                Some(end) => toml_to_chrono(end).unwrap_or(start),
+                None => start.end_of_day(),
+            });
+        }
+    }
+    for event in &config.countdowns.events {
+        boundaries.extend(
+            event
+                .start
+                .iter()
+                .chain([&event.target])
+                .filter_map(|date| toml_to_chrono(date).ok()),
+        );
+    }
+    for calendar in &config.countdowns.calendars {
+        match next_calendar_event(calendar, after) {
+            Ok(event) => boundaries.extend(
+                event
+                    .into_iter()
+                    .flat_map(|event| [event.start, Some(event.target)])
+                    .flatten(),
+            ),
+            Err(e) => log::warn!(
+                "Not scheduling a run for calendar {}: {:#}",
+                calendar.path,
+                e
+            ),
+        }
+    }
+    boundaries
+}
+
+/// The first scheduled run strictly after `after`, if anything is scheduled.
+fn next_run_after(config: &Config, after: DateTime<Local>) -> Option<DateTime<Local>> {
+    let times = times_of_day(config);
+    // Tomorrow's runs are enough, but a time skipped by daylight saving may need the day after
+    let daily = (0..3)
+        .filter_map(|days| after.date_naive().checked_add_days(Days::new(days)))
+        .flat_map(|date| times.iter().map(move |time| date.and_time(*time)))
+        .filter_map(|datetime| Local.from_local_datetime(&datetime).earliest());
+    let boundaries = match config.schedule.phase_boundaries {
+        true => phase_boundaries(config, after),
+        false => Vec::new(),
+    };
+    daily.chain(boundaries).filter(|run| *run > after).min()
+}
+
+/// Renders and sets the wallpapers on the `[schedule]` of the config returned by `load_config`,
+/// until SIGTERM or SIGINT. SIGHUP reloads the config.
+pub(crate) fn run_daemon(
+    dir_path: &str,
+    load_config: impl Fn() -> anyhow::Result<Config>,
+) -> anyhow::Result<()> {
+    let mut signals =
+        Signals::new([SIGHUP, SIGTERM, SIGINT]).context("Failed to register signal handlers")?;
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if sender.send(signal).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut config = load_config()?;
+    let mut refresher = Refresher::new(dir_path, &config)?;
+    let refresh = |refresher: &mut Refresher, config: &Config| {
+        let now = Local::now();
+        refresher.refresh(config, now);
+        // Scheduled once per run, as calendars are read to find their phase boundaries
+        let next = next_run_after(config, now);
+        match next {
+            Some(next) => log::info!("Next run at {}", next.format("%Y-%m-%d %H:%M:%S")),
+            None => log::warn!("Nothing is scheduled; waiting for SIGHUP or SIGTERM"),
+        }
+        next
+    };
+    let mut next = refresh(&mut refresher, &config);
+    loop {
+        let now = Local::now();
+        if let Some(due) = next.filter(|due| *due <= now) {
+            if now - due > chrono::Duration::minutes(1) {
+                log::info!(
+                    "Catching up on the run due at {}",
+                    due.format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+            next = refresh(&mut refresher, &config);
+            continue;
+        }
+
+        let wait = next
+            .and_then(|next| (next - now).to_std().ok())
+            .map_or(MAX_WAIT, |wait| wait.min(MAX_WAIT));
+        match receiver.recv_timeout(wait) {
+            Ok(SIGHUP) => {
+                log::info!("Reloading config.toml");
+                let reloaded = load_config().and_then(|reloaded| {
+                    refresher.reload(&config, &reloaded)?;
+                    Ok(reloaded)
+                });
+                match reloaded {
+                    Ok(reloaded) => {
+                        config = reloaded;
+                        next = refresh(&mut refresher, &config);
+                    }
+                    Err(e) => log::error!("Keeping the previous config: {:#}", e),
+                }
+            }
+            Ok(signal) => {
+                log::info!("Shutting down on signal {}", signal);
+                return Ok(());
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                anyhow::bail!("Stopped receiving signals")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dateutils::parse_local_datetime;
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(&format!("[general]\nttf_font_path = ''\n{}", toml)).unwrap()
+    }
+
+    const COUNTDOWN: &str = "[countdown]\n\
+        term_start = 2024-04-15\n\
+        term_last_lecture = 2024-06-07\n\
+        first_paper = 2024-06-17\n\
+        last_paper_end_time = 2024-07-05T17:00:00\n\
+        [[countdown.papers]]\n\
+        name = 'Algorithms'\n\
+        start = 2024-06-17T09:30:00\n\
+        end = 2024-06-17T12:30:00\n";
+
+    fn next(config: &Config, after: &str) -> String {
+        next_run_after(config, parse_local_datetime(after).unwrap())
+            .unwrap()
+            .format("%Y-%m-%dT%H:%M")
+            .to_string()
+    }
+
+    #[test]
+    fn runs_daily_at_midnight_by_default() {
+        let config = config("");
+        assert_eq!(next(&config, "2024-06-12T09:00:00"), "2024-06-13T00:00");
+        assert_eq!(next(&config, "2024-06-12T00:00:00"), "2024-06-13T00:00");
+    }
+
+    #[test]
+    fn runs_at_daily_times_and_every_n_hours() {
+        let config = config("[schedule]\ndaily = ['07:30', '18:00']\nevery_hours = 5\n");
+        assert_eq!(next(&config, "2024-06-12T06:00:00"), "2024-06-12T07:30");
+        assert_eq!(next(&config, "2024-06-12T07:30:00"), "2024-06-12T10:00");
+        assert_eq!(next(&config, "2024-06-12T17:00:00"), "2024-06-12T18:00");
+        assert_eq!(next(&config, "2024-06-12T20:00:00"), "2024-06-13T00:00");
+    }
+
+    #[test]
+    fn runs_at_phase_boundaries() {
+        let with_boundaries = config(&format!("[schedule]\ndaily = ['08:00']\n{}", COUNTDOWN));
+        // The start and end of the paper, then the end of the exams
+        assert_eq!(
+            next(&with_boundaries, "2024-06-17T08:00:00"),
+            "2024-06-17T09:30"
+        );
+        assert_eq!(
+            next(&with_boundaries, "2024-06-17T09:30:00"),
+            "2024-06-17T12:30"
+        );
+        assert_eq!(
+            next(&with_boundaries, "2024-07-05T08:00:00"),
+            "2024-07-05T17:00"
+        );
+        // term_start is at midnight
+        assert_eq!(
+            next(&with_boundaries, "2024-04-14T08:00:00"),
+            "2024-04-15T00:00"
+        );
+
+        let without_boundaries = config(&format!(
+            "[schedule]\ndaily = ['08:00']\nphase_boundaries = false\n{}",
+            COUNTDOWN
+        ));
+        assert_eq!(
+            next(&without_boundaries, "2024-06-17T08:00:00"),
+            "2024-06-18T08:00"
+        );
+    }
+
+    #[test]
+    fn nothing_scheduled() {
+        let config = config("[schedule]\ndaily = []\nphase_boundaries = false\n");
+        assert_eq!(
+            next_run_after(&config, parse_local_datetime("2024-06-12").unwrap()),
+            None
+        );
+    }
+}
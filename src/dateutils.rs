@@ -1,9 +1,9 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use chrono::{DateTime, Local, NaiveDate, TimeZone};
 use toml::value::Datetime;
 
 pub(crate) fn toml_to_chrono(datetime: &Datetime) -> anyhow::Result<DateTime<Local>> {
-    Ok(Local
+    Local
         .from_local_datetime(
             &NaiveDate::from_ymd_opt(
                 datetime.date.ok_or(anyhow!("missing year"))?.year as i32,
@@ -19,5 +19,54 @@ pub(crate) fn toml_to_chrono(datetime: &Datetime) -> anyhow::Result<DateTime<Loc
             .ok_or(anyhow!("invalid hour, minute and/or second"))?,
         )
         .single()
-        .ok_or(anyhow!("Unable to parse chrono datetime unambiguously: possibly due to negative timezone transition?"))?)
+        .ok_or(anyhow!("Unable to parse chrono datetime unambiguously: possibly due to negative timezone transition?"))
+}
+
+/// Parses a local date or date-time in the same format as config.toml, e.g. `2024-05-20` or
+/// `2024-05-20T09:00:00`.
+pub(crate) fn parse_local_datetime(s: &str) -> anyhow::Result<DateTime<Local>> {
+    let datetime: Datetime = s.trim().parse().map_err(|e| {
+        anyhow!(
+            "{} is not a date (e.g. 2024-05-20) or date-time (e.g. 2024-05-20T09:00:00): {}",
+            s,
+            e
+        )
+    })?;
+    if datetime.offset.is_some() {
+        bail!("{} must be a local date-time, without a timezone offset", s);
+    }
+    toml_to_chrono(&datetime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    #[test]
+    fn parses_date_as_midnight() {
+        let datetime = parse_local_datetime("2024-05-20").unwrap();
+        assert_eq!(
+            datetime.date_naive(),
+            NaiveDate::from_ymd_opt(2024, 5, 20).unwrap()
+        );
+        assert_eq!((datetime.hour(), datetime.minute()), (0, 0));
+    }
+
+    #[test]
+    fn parses_date_time() {
+        let datetime = parse_local_datetime("2024-05-20T09:30:00").unwrap();
+        assert_eq!(
+            datetime.date_naive(),
+            NaiveDate::from_ymd_opt(2024, 5, 20).unwrap()
+        );
+        assert_eq!((datetime.hour(), datetime.minute()), (9, 30));
+    }
+
+    #[test]
+    fn rejects_offsets_and_garbage() {
+        assert!(parse_local_datetime("2024-05-20T09:30:00Z").is_err());
+        assert!(parse_local_datetime("09:30:00").is_err());
+        assert!(parse_local_datetime("tomorrow").is_err());
+    }
 }
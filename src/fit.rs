@@ -0,0 +1,146 @@
+use globset::GlobMatcher;
+use image::{
+    imageops::{replace, tile, FilterType},
+    DynamicImage, Rgba, RgbaImage,
+};
+
+use crate::{
+    config::{ConfigFit, CropStrategy, FitMode},
+    imageops::Screen,
+    scan::glob,
+    smartcrop::{Crop, FocalPoints},
+};
+
+/// The background behind contained images is blurred at this fraction of the screen size, which
+/// is much faster than blurring at full size.
+const BLUR_DOWNSCALE_FACTOR: u32 = 8;
+const BLUR_SIGMA: f32 = 4.0;
+/// Brightness (out of 255) the blurred background is darkened to.
+const BLUR_BRIGHTNESS: u32 = 128;
+
+/// How a chosen image is fitted to a screen.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Framing {
+    pub(crate) mode: FitMode,
+    /// Which part of the image is kept; only used by FitMode::Cover
+    pub(crate) crop: Crop,
+    /// Colour of the space not covered by the image
+    pub(crate) fill_colour: Rgba<u8>,
+}
+
+/// Resolves the framing of each image from `[fit]`, `[crop]` and the focal points sidecar.
+pub(crate) struct FramingResolver<'a> {
+    config: &'a ConfigFit,
+    overrides: Vec<(GlobMatcher, FitMode)>,
+    focal_points: &'a FocalPoints,
+    crop_strategy: CropStrategy,
+}
+
+impl<'a> FramingResolver<'a> {
+    pub(crate) fn new(
+        config: &'a ConfigFit,
+        crop_strategy: CropStrategy,
+        focal_points: &'a FocalPoints,
+    ) -> anyhow::Result<Self> {
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|o| Ok((glob(&o.pattern)?.compile_matcher(), o.mode)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            config,
+            overrides,
+            focal_points,
+            crop_strategy,
+        })
+    }
+
+    pub(crate) fn framing_for(&self, image_name: &str) -> Framing {
+        // The first matching override wins
+        let mode = self
+            .overrides
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(image_name))
+            .map_or(self.config.mode, |(_, mode)| *mode);
+        let [r, g, b] = self.config.fill_colour;
+        Framing {
+            mode,
+            crop: self.focal_points.crop_for(image_name, self.crop_strategy),
+            fill_colour: Rgba([r, g, b, 255]),
+        }
+    }
+}
+
+/// Scales `img` to be as large as possible while fitting inside `screen`.
+fn resize_to_fit_inside_screen(img: &DynamicImage, screen: Screen) -> DynamicImage {
+    img.resize(screen.width_px, screen.height_px, FilterType::CatmullRom)
+}
+
+/// Places `foreground` at the centre of `background`.
+fn place_at_centre(background: &mut RgbaImage, foreground: &RgbaImage) {
+    let x = (background.width() as i64 - foreground.width() as i64) / 2;
+    let y = (background.height() as i64 - foreground.height() as i64) / 2;
+    replace(background, foreground, x, y);
+}
+
+/// The whole image, on top of a blurred, darkened copy of itself covering the screen.
+/// `covered` is `img` already fitted with FitMode::Cover.
+pub(crate) fn contain_blur(
+    img: &DynamicImage,
+    covered: DynamicImage,
+    screen: Screen,
+) -> DynamicImage {
+    let mut background = covered
+        .resize_exact(
+            (screen.width_px / BLUR_DOWNSCALE_FACTOR).max(1),
+            (screen.height_px / BLUR_DOWNSCALE_FACTOR).max(1),
+            FilterType::Triangle,
+        )
+        .blur(BLUR_SIGMA)
+        .resize_exact(screen.width_px, screen.height_px, FilterType::Triangle)
+        .to_rgba8();
+    for pixel in background.pixels_mut() {
+        for channel in pixel.0.iter_mut().take(3) {
+            *channel = (*channel as u32 * BLUR_BRIGHTNESS / 255) as u8;
+        }
+    }
+    place_at_centre(
+        &mut background,
+        &resize_to_fit_inside_screen(img, screen).to_rgba8(),
+    );
+    DynamicImage::ImageRgba8(background)
+}
+
+/// The whole image, with the remaining space filled with `fill_colour`.
+pub(crate) fn letterbox(img: &DynamicImage, screen: Screen, fill_colour: Rgba<u8>) -> DynamicImage {
+    let mut background = RgbaImage::from_pixel(screen.width_px, screen.height_px, fill_colour);
+    place_at_centre(
+        &mut background,
+        &resize_to_fit_inside_screen(img, screen).to_rgba8(),
+    );
+    DynamicImage::ImageRgba8(background)
+}
+
+/// The image repeated from the top-left corner, unscaled.
+pub(crate) fn tile_unscaled(img: &DynamicImage, screen: Screen) -> DynamicImage {
+    let mut background = RgbaImage::new(screen.width_px, screen.height_px);
+    tile(&mut background, &img.to_rgba8());
+    DynamicImage::ImageRgba8(background)
+}
+
+/// The image at the centre, unscaled (cropped if larger than the screen), with the remaining space
+/// filled with `fill_colour`.
+pub(crate) fn centre_unscaled(
+    img: &DynamicImage,
+    screen: Screen,
+    fill_colour: Rgba<u8>,
+) -> DynamicImage {
+    let mut background = RgbaImage::from_pixel(screen.width_px, screen.height_px, fill_colour);
+    place_at_centre(&mut background, &img.to_rgba8());
+    DynamicImage::ImageRgba8(background)
+}
+
+/// The image scaled to the screen, ignoring its aspect ratio.
+pub(crate) fn stretch(img: &DynamicImage, screen: Screen) -> DynamicImage {
+    img.resize_exact(screen.width_px, screen.height_px, FilterType::CatmullRom)
+}
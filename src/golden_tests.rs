@@ -0,0 +1,238 @@
+// Golden-image tests for `process_base_image` and `draw_time_layers`.
+//
+// Each case renders a generated input image with the bundled test font at a fixed time, and
+// compares the result with `tests/golden/<CASE>.png`. On a mismatch, the actual image and a diff
+// image (mismatched pixels in red over a dimmed copy of the golden) are written to
+// `target/golden-diffs`. Run with `UPDATE_GOLDEN=1` to accept the new output.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use image::{DynamicImage, Rgba, RgbaImage};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    config::Config,
+    dateutils::parse_local_datetime,
+    fit::FramingResolver,
+    imageops::{countdown_blocks, draw_time_layers, load_font, process_base_image},
+    smartcrop::FocalPoints,
+};
+
+/// Largest per-channel difference allowed between a rendered pixel and its golden, to absorb
+/// rounding differences between platforms.
+const CHANNEL_TOLERANCE: u8 = 2;
+const SEED: u64 = 20240415;
+
+fn manifest_path(relative: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+fn config(extra_toml: &str) -> Config {
+    let font_path = manifest_path("tests/fixtures/DejaVuSans.ttf");
+    let toml = format!(
+        "[general]\n\
+         ttf_font_path = '{}'\n\
+         screen_width_px = 960\n\
+         screen_height_px = 540\n\
+         {}",
+        font_path.to_string_lossy(),
+        extra_toml
+    );
+    toml::from_str(&toml).unwrap()
+}
+
+const COUNTDOWN: &str = "[countdown]\n\
+    term_start = 2024-04-15\n\
+    term_last_lecture = 2024-06-07\n\
+    first_paper = 2024-06-17\n\
+    last_paper_end_time = 2024-07-05T17:00:00\n";
+
+/// Random coloured blocks, which (unlike noise) compress well in the golden PNGs.
+fn input_image(width: u32, height: u32) -> DynamicImage {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut img = RgbaImage::from_pixel(width, height, Rgba([40, 60, 90, 255]));
+    for _ in 0..24 {
+        let (x, y) = (rng.gen_range(0..width), rng.gen_range(0..height));
+        let (w, h) = (rng.gen_range(1..width / 3), rng.gen_range(1..height / 3));
+        let colour = Rgba([rng.gen(), rng.gen(), rng.gen(), 255]);
+        for py in y..(y + h).min(height) {
+            for px in x..(x + w).min(width) {
+                img.put_pixel(px, py, colour);
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(img)
+}
+
+fn render(config: &Config, input: DynamicImage, now: &str) -> RgbaImage {
+    let font = load_font(config).unwrap();
+    let focal_points = FocalPoints::default();
+    let framing = FramingResolver::new(&config.fit, config.crop.strategy, &focal_points)
+        .unwrap()
+        .framing_for("golden.png");
+    let now: DateTime<Local> = parse_local_datetime(now).unwrap();
+    let monitor = &config.monitors()[0];
+    let base = process_base_image(input, "golden.png", framing, monitor, &font).unwrap();
+    let blocks = countdown_blocks(config, now).unwrap();
+    draw_time_layers(&base, monitor, config, &font, &blocks).unwrap()
+}
+
+/// Number of pixels differing from the golden by more than the tolerance, and the diff image.
+fn compare(actual: &RgbaImage, golden: &RgbaImage) -> (usize, RgbaImage) {
+    let mut mismatches = 0;
+    let diff = RgbaImage::from_fn(golden.width(), golden.height(), |x, y| {
+        let expected = golden.get_pixel(x, y);
+        let matches = actual.get_pixel(x, y).0.iter().zip(expected.0.iter()).all(
+            |(actual_channel, expected_channel)| {
+                actual_channel.abs_diff(*expected_channel) <= CHANNEL_TOLERANCE
+            },
+        );
+        if matches {
+            let [r, g, b, _] = expected.0;
+            Rgba([r / 4, g / 4, b / 4, 255])
+        } else {
+            mismatches += 1;
+            Rgba([255, 0, 0, 255])
+        }
+    });
+    (mismatches, diff)
+}
+
+fn assert_matches_golden(name: &str, actual: &RgbaImage) {
+    let golden_path = manifest_path(&format!("tests/golden/{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&golden_path).unwrap();
+        return;
+    }
+    let golden = image::open(&golden_path)
+        .unwrap_or_else(|e| {
+            panic!(
+                "Failed to open {} ({}); run with UPDATE_GOLDEN=1 to create it",
+                golden_path.to_string_lossy(),
+                e
+            )
+        })
+        .to_rgba8();
+
+    let diff_dir = manifest_path("target/golden-diffs");
+    let actual_path = diff_dir.join(format!("{}-actual.png", name));
+    let diff_path = diff_dir.join(format!("{}-diff.png", name));
+    if actual.dimensions() != golden.dimensions() {
+        std::fs::create_dir_all(&diff_dir).unwrap();
+        actual.save(&actual_path).unwrap();
+        panic!(
+            "{} is {:?}, but its golden is {:?}; see {}",
+            name,
+            actual.dimensions(),
+            golden.dimensions(),
+            actual_path.to_string_lossy()
+        );
+    }
+    let (mismatches, diff) = compare(actual, &golden);
+    if mismatches > 0 {
+        std::fs::create_dir_all(&diff_dir).unwrap();
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{} differs from its golden in {} pixels; see {} and {}, or run with UPDATE_GOLDEN=1 \
+             if the change is intended",
+            name,
+            mismatches,
+            actual_path.to_string_lossy(),
+            diff_path.to_string_lossy()
+        );
+    }
+}
+
+#[test]
+fn image_name_only() {
+    let config = config("");
+    let actual = render(&config, input_image(1200, 800), "2024-05-20T09:00:00");
+    assert_matches_golden("image_name_only", &actual);
+}
+
+#[test]
+fn countdown_during_term() {
+    let config = config(COUNTDOWN);
+    let actual = render(&config, input_image(1200, 800), "2024-05-20T09:00:00");
+    assert_matches_golden("countdown_during_term", &actual);
+}
+
+#[test]
+fn countdown_during_exams_with_overlay() {
+    let config = config(&format!("{}[overlay]\ntext = 'FOCUS'\n", COUNTDOWN));
+    let actual = render(&config, input_image(1200, 800), "2024-06-20T09:00:00");
+    assert_matches_golden("countdown_during_exams_with_overlay", &actual);
+}
+
+#[test]
+fn insets_and_contain_blur() {
+    let config = config(&format!(
+        "{}[overlay]\ntext = 'FOCUS'\n\
+         [fit]\nmode = 'contain_blur'\n\
+         [layout]\ntop = 60\nbottom = 80\nleft = 100\nright = 40\n",
+        COUNTDOWN
+    ));
+    let actual = render(&config, input_image(600, 800), "2024-06-12T09:00:00");
+    assert_matches_golden("insets_and_contain_blur", &actual);
+}
+
+#[test]
+fn stacked_countdowns_top_right() {
+    let config = config(&format!(
+        "{}[countdowns]\ncorner = 'top_right'\n\
+         [[countdowns.events]]\nlabel = 'Launch'\ntarget = 2024-06-21T12:00:00\n\
+         start = 2024-06-01\n\
+         [[countdowns.events]]\nlabel = 'Trip'\ntarget = 2024-08-03T07:00:00\n",
+        COUNTDOWN
+    ));
+    let actual = render(&config, input_image(1200, 800), "2024-06-12T09:00:00");
+    assert_matches_golden("stacked_countdowns_top_right", &actual);
+}
+
+#[test]
+fn progress_bar_during_term() {
+    let config = config(&format!("{}style = 'progress_bar'\n", COUNTDOWN));
+    let actual = render(&config, input_image(1200, 800), "2024-05-20T09:00:00");
+    assert_matches_golden("progress_bar_during_term", &actual);
+}
+
+#[test]
+fn calendar_widgets() {
+    let config = config(&format!(
+        "{}style = 'calendar'\n\
+         [countdowns]\ncorner = 'top_right'\n\
+         [[countdowns.events]]\nlabel = 'Trip'\ntarget = 2024-08-03T07:00:00\n\
+         style = 'calendar'\n",
+        COUNTDOWN
+    ));
+    let actual = render(&config, input_image(1200, 800), "2024-06-12T09:00:00");
+    assert_matches_golden("calendar_widgets", &actual);
+}
+
+#[test]
+fn timetable_during_exams() {
+    let config = config(&format!(
+        "{}[[countdown.papers]]\nname = 'Algorithms'\nstart = 2024-06-17T09:30:00\n\
+         end = 2024-06-17T12:30:00\nlocation = 'Exam Hall'\n\
+         [[countdown.papers]]\nname = 'Compilers'\nstart = 2024-06-20T14:00:00\n\
+         end = 2024-06-20T17:00:00\nlocation = 'Room 2'\n\
+         [[countdown.papers]]\nname = 'Databases'\nstart = 2024-06-26T09:30:00\n\
+         end = 2024-06-26T12:30:00\nlocation = 'Exam Hall'\n",
+        COUNTDOWN
+    ));
+    let actual = render(&config, input_image(1200, 800), "2024-06-20T09:00:00");
+    assert_matches_golden("timetable_during_exams", &actual);
+}
+
+#[test]
+fn week_of_term() {
+    let config = config(&format!(
+        "{}[countdown.weeks]\n\
+         [[countdown.weeks.breaks]]\nstart = 2024-05-13\nend = 2024-05-17\n",
+        COUNTDOWN
+    ));
+    let actual = render(&config, input_image(1200, 800), "2024-05-20T09:00:00");
+    assert_matches_golden("week_of_term", &actual);
+}
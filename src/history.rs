@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+/// Older entries are dropped once the history grows beyond this many picks.
+const HISTORY_MAX_LEN: usize = 1000;
+
+/// Record of previously chosen images, persisted as one image name per line in
+/// `<DIR>/Working/history.txt` (oldest first).
+#[derive(Debug)]
+pub(crate) struct History {
+    path: PathBuf,
+    entries: Vec<String>,
+}
+
+impl History {
+    pub(crate) fn read_from_dir(parent_dir: &str) -> anyhow::Result<Self> {
+        let path: PathBuf = [parent_dir, "Working", "history.txt"].iter().collect();
+        let entries = if path.exists() {
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read history from {}", path.to_string_lossy()))?
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, entries })
+    }
+
+    /// Names of the `n` most recent picks, most recent first.
+    pub(crate) fn recent(&self, n: usize) -> Vec<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .take(n)
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub(crate) fn record(&mut self, image_name: &str) {
+        self.entries.push(image_name.to_string());
+        if self.entries.len() > HISTORY_MAX_LEN {
+            self.entries.drain(..self.entries.len() - HISTORY_MAX_LEN);
+        }
+    }
+
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let mut contents = self.entries.join("\n");
+        contents.push('\n');
+        std::fs::write(&self.path, contents)
+            .with_context(|| format!("Failed to save history to {}", self.path.to_string_lossy()))
+    }
+}
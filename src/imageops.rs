@@ -1,190 +1,627 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{anyhow, bail, Context};
-use image::{imageops::FilterType, DynamicImage, Pixel, Rgba, RgbaImage};
+use chrono::{DateTime, Local};
+use image::{imageops::FilterType, DynamicImage, ImageError, Pixel, Rgba, RgbaImage};
 use imageproc::drawing::text_size;
-use rand::seq::IteratorRandom;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    seq::SliceRandom,
+    Rng,
+};
 use rusttype::{Font, Scale};
 
 use crate::{
-    config::Config,
+    calendar::next_calendar_event,
+    config::{Config, Corner, FitMode, SelectionMode},
     countdown::{
-        generate_today_string, get_countdown_str, get_font_fill_colour, get_font_stroke_colour,
+        event_block, event_ramp, term_block, timetable_block, CountdownBlock, CountdownEvent,
     },
-    imageutils::draw_text_with_border_mut,
+    fit::{centre_unscaled, contain_blur, letterbox, stretch, tile_unscaled, Framing},
+    history::History,
+    imageutils::{apply_exif_orientation, draw_text_with_border_mut, read_exif_orientation},
+    quarantine::Quarantine,
+    ratings::Ratings,
+    render::Monitor,
+    scan::{find_images, image_key},
+    shuffle_bag::ShuffleBag,
+    smartcrop::{crop_offset, Crop},
+    widgets::Widget,
+    workdays::WorkingDays,
 };
 
-pub(crate) const SCREEN_WIDTH_PX: u32 = 1920;
-pub(crate) const SCREEN_HEIGHT_PX: u32 = 1080;
-
-pub(crate) fn choose_one_image(dir_path: &str) -> anyhow::Result<String> {
-    let all_paths = std::fs::read_dir(dir_path)
-        .with_context(|| format!("Directory {} does not exist", dir_path))?;
-    let image_paths = all_paths
-        .filter_map(|path| path.ok())
-        .filter(|path| path.metadata().is_ok_and(|metadata| metadata.is_file()));
-    image_paths
-        .choose(&mut rand::thread_rng())
-        .map(|e| e.path().display().to_string())
-        .ok_or(anyhow!("Directory {} is empty", dir_path))
+/// Layout lengths (font sizes, margins, etc.) are specified for this resolution, and scaled
+/// proportionally for the actual screen.
+const REFERENCE_WIDTH_PX: u32 = 1920;
+const REFERENCE_HEIGHT_PX: u32 = 1080;
+
+/// Space kept between the overlay text and the insets, at the reference resolution.
+pub(crate) const TEXT_MARGIN_PX: i32 = 24;
+
+/// Resolution of the generated background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Screen {
+    pub(crate) width_px: u32,
+    pub(crate) height_px: u32,
+}
+
+impl Screen {
+    /// Converts a length specified at the reference resolution to this screen. The smaller of the
+    /// two axis ratios is used, so that layouts designed for the reference resolution still fit.
+    pub(crate) fn scale(&self, reference_length: f32) -> f32 {
+        let ratio = f32::min(
+            self.width_px as f32 / REFERENCE_WIDTH_PX as f32,
+            self.height_px as f32 / REFERENCE_HEIGHT_PX as f32,
+        );
+        reference_length * ratio
+    }
+
+    pub(crate) fn scale_px(&self, reference_px: i32) -> i32 {
+        self.scale(reference_px as f32).round() as i32
+    }
+
+    /// Like scale_px, but for text outline widths, which are at least 1 px.
+    pub(crate) fn scale_outline_px(&self, reference_px: u8) -> u8 {
+        self.scale_px(reference_px as i32).clamp(1, u8::MAX as i32) as u8
+    }
+}
+
+/// Parses a resolution of the form `<WIDTH>x<HEIGHT>`, e.g. `2560x1440`.
+pub(crate) fn parse_resolution(s: &str) -> anyhow::Result<Screen> {
+    let (width, height) = s
+        .split_once(['x', 'X'])
+        .with_context(|| format!("{} is not of the form <WIDTH>x<HEIGHT>", s))?;
+    let screen = Screen {
+        width_px: width
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid width {}", width))?,
+        height_px: height
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid height {}", height))?,
+    };
+    if screen.width_px == 0 || screen.height_px == 0 {
+        bail!("Resolution {} must be non-zero", s);
+    }
+    Ok(screen)
+}
+
+/// Retries with a different image (quarantining the bad one) this many times before giving up.
+const MAX_DECODE_ATTEMPTS: usize = 10;
+
+/// Whether loading an image failed because the file couldn't be read (e.g. a network mount that
+/// isn't up yet, or missing permissions), rather than because it isn't a valid image. Such images
+/// are only skipped for this run, not quarantined.
+fn is_read_error(e: &anyhow::Error) -> bool {
+    let io_error = match e.downcast_ref::<ImageError>() {
+        Some(ImageError::IoError(io_error)) => io_error,
+        Some(_) => return false,
+        None => match e.downcast_ref::<std::io::Error>() {
+            Some(io_error) => io_error,
+            None => return false,
+        },
+    };
+    // A truncated or garbled file is read fine, but isn't a valid image
+    !matches!(
+        io_error.kind(),
+        ErrorKind::UnexpectedEof | ErrorKind::InvalidData
+    )
+}
+
+/// Whether choosing images updates the selection state (`history.txt`, `shuffle_bag.toml` and
+/// `quarantine.toml`), or leaves it as it is, e.g. when only rendering a preview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Selection {
+    Record,
+    DryRun,
+}
+
+/// Chooses an image and decodes it. Images that fail to decode are quarantined, and another image
+/// is chosen. Only a successfully decoded image is recorded in the history.
+pub(crate) fn choose_and_load_image(
+    dir_path: &str,
+    config: &Config,
+    selection: Selection,
+) -> anyhow::Result<(String, DynamicImage)> {
+    let mut history = History::read_from_dir(dir_path)?;
+    let mut quarantine = Quarantine::read_from_dir(dir_path)?;
+    let mut skipped = HashSet::new();
+    for _ in 0..MAX_DECODE_ATTEMPTS {
+        let chosen_img_path =
+            choose_one_image(dir_path, config, &history, &quarantine, &skipped, selection)?;
+        let image_name = image_key(dir_path, Path::new(&chosen_img_path));
+        match load_image(&chosen_img_path) {
+            Ok(img) => {
+                if selection == Selection::Record {
+                    history.record(&image_name);
+                    history.save()?;
+                }
+                return Ok((chosen_img_path, img));
+            }
+            Err(e) if is_read_error(&e) => {
+                log::error!(
+                    "Failed to read {}, skipping it this time: {:#}",
+                    chosen_img_path,
+                    e
+                );
+                skipped.insert(image_name);
+            }
+            Err(e) if selection == Selection::DryRun => {
+                log::error!(
+                    "Failed to decode {}, skipping it (a dry run doesn't quarantine): {:#}",
+                    chosen_img_path,
+                    e
+                );
+                skipped.insert(image_name);
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to decode {}, quarantining it: {:#}",
+                    chosen_img_path,
+                    e
+                );
+                quarantine.add(&image_name, format!("{:#}", e));
+                quarantine.save()?;
+                if config.selection.move_quarantined {
+                    quarantine.move_to_folder(&image_name)?;
+                }
+            }
+        }
+    }
+    bail!(
+        "Failed to load {} images in a row; see the log and {}",
+        MAX_DECODE_ATTEMPTS,
+        [dir_path, "Working", "quarantine.toml"]
+            .iter()
+            .collect::<PathBuf>()
+            .to_string_lossy()
+    )
+}
+
+pub(crate) fn choose_one_image(
+    dir_path: &str,
+    config: &Config,
+    history: &History,
+    quarantine: &Quarantine,
+    skipped: &HashSet<String>,
+    selection: Selection,
+) -> anyhow::Result<String> {
+    let image_paths: Vec<PathBuf> = find_images(dir_path, &config.scan)?
+        .into_iter()
+        .filter(|path| {
+            let image_name = image_key(dir_path, path);
+            !quarantine.contains(&image_name) && !skipped.contains(&image_name)
+        })
+        .collect();
+    if image_paths.is_empty() {
+        bail!(
+            "Directory {} contains no images (that aren't quarantined or unreadable)",
+            dir_path
+        );
+    }
+    let image_names: Vec<String> = image_paths
+        .iter()
+        .map(|path| image_key(dir_path, path))
+        .collect();
+
+    let mut rng = rand::thread_rng();
+    let chosen_name = match config.selection.mode {
+        SelectionMode::Uniform => {
+            choose_avoiding_recent(&image_names, history, config, |eligible| {
+                eligible
+                    .choose(&mut rng)
+                    .map(|image_name| image_name.to_string())
+            })
+        }
+        SelectionMode::Weighted => {
+            let ratings = Ratings::read_from_dir(dir_path)?;
+            choose_avoiding_recent(&image_names, history, config, |eligible| {
+                choose_weighted(eligible, &ratings, &mut rng)
+            })
+        }
+        SelectionMode::ShuffleBag => {
+            let mut bag = ShuffleBag::read_from_dir(dir_path)?;
+            bag.sync(&image_names, &mut rng);
+            let chosen_name = bag.pop(
+                &image_names,
+                &history.recent(config.selection.no_repeat_within),
+                &mut rng,
+            );
+            if selection == Selection::Record {
+                bag.save()?;
+            }
+            chosen_name
+        }
+    }
+    .ok_or(anyhow!(
+        "No image in directory {} can be chosen (are all weights 0?)",
+        dir_path
+    ))?;
+
+    let chosen_path = image_paths
+        .iter()
+        .zip(image_names.iter())
+        .find_map(|(path, name)| (*name == chosen_name).then_some(path))
+        .ok_or(anyhow!("Chosen image {} disappeared", chosen_name))?;
+    Ok(chosen_path.display().to_string())
+}
+
+/// Picks among images not chosen recently using `pick`. If the pool is too small to honour
+/// no_repeat_within, the window is shrunk until `pick` succeeds; with a window of 0 every image is
+/// eligible.
+fn choose_avoiding_recent(
+    image_names: &[String],
+    history: &History,
+    config: &Config,
+    mut pick: impl FnMut(&[&String]) -> Option<String>,
+) -> Option<String> {
+    (0..=config.selection.no_repeat_within)
+        .rev()
+        .find_map(|window| {
+            let recent = history.recent(window);
+            let eligible: Vec<&String> = image_names
+                .iter()
+                .filter(|image_name| !recent.contains(&image_name.as_str()))
+                .collect();
+            let chosen = pick(&eligible);
+            if chosen.is_some() && window < config.selection.no_repeat_within {
+                log::info!(
+                    "Only {} image(s) available; relaxed no_repeat_within from {} to {}",
+                    image_names.len(),
+                    config.selection.no_repeat_within,
+                    window
+                );
+            }
+            chosen
+        })
+}
+
+fn choose_weighted(eligible: &[&String], ratings: &Ratings, rng: &mut impl Rng) -> Option<String> {
+    let weights = eligible
+        .iter()
+        .map(|image_name| ratings.weight_of(image_name));
+    // Fails if there are no eligible images or all of them have weight 0
+    let distribution = WeightedIndex::new(weights).ok()?;
+    Some(eligible[distribution.sample(rng)].clone())
 }
 
 /// No cropping is done!
 /// Numerical operations should be sound: overflows won't happen in the range we're handling.
-fn resize_to_contain_screen(img: DynamicImage) -> DynamicImage {
+fn resize_to_contain_screen(img: DynamicImage, screen: Screen) -> DynamicImage {
     let width = img.width();
     let height = img.height();
     log::debug!("Image original width={}, height={}", width, height);
-    if width as f64 / height as f64 <= SCREEN_WIDTH_PX as f64 / SCREEN_HEIGHT_PX as f64 {
-        // in terms of aspect ratio, image too tall; fit width to SCREEN_WIDTH_PX
-        log::debug!("Resizing: image too tall, fitting width to SCREEN_WIDTH_PX");
-        if width == SCREEN_WIDTH_PX {
+    if width as f64 / height as f64 <= screen.width_px as f64 / screen.height_px as f64 {
+        // in terms of aspect ratio, image too tall; fit width to screen width
+        log::debug!("Resizing: image too tall, fitting width to screen width");
+        if width == screen.width_px {
             log::debug!("Resizing: Early return");
             return img;
         }
-        let scale_ratio = SCREEN_WIDTH_PX as f64 / width as f64;
+        let scale_ratio = screen.width_px as f64 / width as f64;
         img.resize(
-            SCREEN_WIDTH_PX,
+            screen.width_px,
             ((height as f64) * scale_ratio).ceil() as u32,
             FilterType::CatmullRom,
         )
     } else {
-        // in terms of aspect ratio, image too long; fit height to SCREEN_HEIGHT_PX
-        log::debug!("Resizing: image too long, fitting width to SCREEN_HEIGHT_PX");
-        if height == SCREEN_HEIGHT_PX {
+        // in terms of aspect ratio, image too long; fit height to screen height
+        log::debug!("Resizing: image too long, fitting height to screen height");
+        if height == screen.height_px {
             log::debug!("Resizing: Early return");
             return img;
         }
-        let scale_ratio = SCREEN_HEIGHT_PX as f64 / height as f64;
+        let scale_ratio = screen.height_px as f64 / height as f64;
         img.resize(
             ((width as f64) * scale_ratio).ceil() as u32,
-            SCREEN_HEIGHT_PX,
+            screen.height_px,
             FilterType::CatmullRom,
         )
     }
 }
 
 /// Assumes you ran resize_to_contain_screen already
-fn crop_to_fit_screen_exactly(mut img: DynamicImage) -> DynamicImage {
+fn crop_to_fit_screen_exactly(mut img: DynamicImage, screen: Screen, crop: Crop) -> DynamicImage {
     let width = img.width();
     let height = img.height();
     log::debug!(
-        "[After resizing, before cropping] width={} (SCREEN_WIDTH_PX={}), height={} (SCREEN_HEIGHT_PX={})",
+        "[After resizing, before cropping] width={} (screen width={}), height={} (screen height={})",
         width,
-        SCREEN_WIDTH_PX,
+        screen.width_px,
         height,
-        SCREEN_HEIGHT_PX
+        screen.height_px
     );
-    if height == SCREEN_HEIGHT_PX && width == SCREEN_WIDTH_PX {
+    if height == screen.height_px && width == screen.width_px {
         return img;
     }
-    if height == SCREEN_HEIGHT_PX {
-        // image too long, grab horizontal center
-        img.crop(
-            width / 2 - SCREEN_WIDTH_PX / 2,
-            0,
-            SCREEN_WIDTH_PX,
-            SCREEN_HEIGHT_PX,
-        )
-    } else if width == SCREEN_WIDTH_PX {
-        // image too tall, grab vertical center
-        img.crop(
-            0,
-            height / 2 - SCREEN_HEIGHT_PX / 2,
-            SCREEN_WIDTH_PX,
-            SCREEN_HEIGHT_PX,
-        )
+    if height == screen.height_px {
+        // image too long, grab a horizontal window (the center, unless configured otherwise)
+        let x = crop_offset(&img, screen, crop);
+        img.crop(x, 0, screen.width_px, screen.height_px)
+    } else if width == screen.width_px {
+        // image too tall, grab a vertical window (the center, unless configured otherwise)
+        let y = crop_offset(&img, screen, crop);
+        img.crop(0, y, screen.width_px, screen.height_px)
     } else {
         panic!("Impossible branch")
     }
 }
 
-pub(crate) fn process_image(
-    image_path: &str,
-    final_path: &str,
-    config: &Config,
-) -> anyhow::Result<()> {
-    log::info!("Processing (reading) image: {}", image_path);
-    let mut img = image::io::Reader::open(image_path)?.decode()?;
-    log::info!("Resizing image");
-    img = resize_to_contain_screen(img);
-    log::info!("Cropping image");
-    img = crop_to_fit_screen_exactly(img);
-    let mut img = img.to_rgba8();
+/// Decodes an image, rotated/flipped upright according to its EXIF orientation. All later
+/// (aspect-ratio-dependent) processing therefore sees the dimensions as displayed.
+pub(crate) fn load_image(image_path: &str) -> anyhow::Result<DynamicImage> {
+    log::info!("Reading image: {}", image_path);
+    let img = image::io::Reader::open(image_path)?.decode()?;
+    Ok(apply_exif_orientation(
+        img,
+        read_exif_orientation(image_path),
+    ))
+}
 
+pub(crate) fn load_font(config: &Config) -> anyhow::Result<Font<'static>> {
     log::info!("Loading font");
-    let font_data = std::fs::read(&config.general.ttf_font_path)?;
-    let font = rusttype::Font::try_from_vec(font_data).ok_or(anyhow!(
+    let font_data = std::fs::read(&config.general.ttf_font_path)
+        .with_context(|| format!("Failed to read font from {}", config.general.ttf_font_path))?;
+    rusttype::Font::try_from_vec(font_data).ok_or(anyhow!(
         "Invalid font provided at {}",
         config.general.ttf_font_path
-    ))?;
+    ))
+}
+
+/// Draws `blocks` stacked outwards from `corner` of the monitor's safe area, the first block
+/// nearest the corner. In the bottom-right corner, the stack starts above the image name.
+fn draw_countdown_blocks(
+    img: &mut RgbaImage,
+    blocks: &[CountdownBlock],
+    corner: Corner,
+    monitor: &Monitor,
+    font: &Font,
+    image_name_height: i32,
+) {
+    let screen = monitor.screen;
+    let margin = screen.scale_px(12);
+    let caption_font_scale = Scale::uniform(screen.scale(20.0));
+    let value_font_scale = Scale::uniform(screen.scale(200.0));
+    let subtitle_font_scale = Scale::uniform(screen.scale(40.0));
+    let left = monitor.layout.left as i32 + margin;
+    let right = screen.width_px as i32 - monitor.layout.right as i32 - margin;
+    let top = monitor.layout.top as i32 + margin;
+    let mut bottom = screen.height_px as i32 - monitor.layout.bottom as i32 - margin;
+    if corner == Corner::BottomRight {
+        bottom -= image_name_height + margin;
+    }
+
+    // Distance from the corner taken up by the blocks drawn so far
+    let mut stacked_height = 0;
+    for block in blocks {
+        let caption_size = text_size(caption_font_scale, font, &block.caption);
+        let widget_size = match &block.widget {
+            Widget::Number => text_size(value_font_scale, font, &block.value),
+            Widget::ProgressBar(bar) => bar.size(screen),
+            Widget::Calendar(grid) => grid.size(screen),
+            Widget::Timetable(timetable) => timetable.size(screen, font),
+        };
+        let subtitle_size = block
+            .subtitle
+            .as_ref()
+            .map(|subtitle| text_size(subtitle_font_scale, font, subtitle));
+        let subtitle_height = subtitle_size.map_or(0, |(_, height)| height + margin);
+        let block_height = widget_size.1 + margin + subtitle_height + caption_size.1;
+        let widget_y = match corner {
+            Corner::BottomLeft | Corner::BottomRight => bottom - stacked_height - block_height,
+            Corner::TopLeft | Corner::TopRight => top + stacked_height,
+        };
+        let x_for_width = |width: i32| match corner {
+            Corner::BottomLeft | Corner::TopLeft => left,
+            Corner::BottomRight | Corner::TopRight => right - width,
+        };
+        draw_text_with_border_mut(
+            img,
+            block.fill_colour,
+            x_for_width(caption_size.0),
+            widget_y + widget_size.1 + margin + subtitle_height,
+            caption_font_scale,
+            font,
+            &block.caption,
+            block.stroke_colour,
+            screen.scale_outline_px(2),
+        );
+        if let (Some(subtitle), Some(subtitle_size)) = (&block.subtitle, subtitle_size) {
+            draw_text_with_border_mut(
+                img,
+                block.fill_colour,
+                x_for_width(subtitle_size.0),
+                widget_y + widget_size.1 + margin,
+                subtitle_font_scale,
+                font,
+                subtitle,
+                block.stroke_colour,
+                screen.scale_outline_px(3),
+            );
+        }
+        let widget_position = (x_for_width(widget_size.0), widget_y);
+        match &block.widget {
+            Widget::Number => draw_text_with_border_mut(
+                img,
+                block.fill_colour,
+                widget_position.0,
+                widget_position.1,
+                value_font_scale,
+                font,
+                &block.value,
+                block.stroke_colour,
+                screen.scale_outline_px(6),
+            ),
+            Widget::ProgressBar(bar) => bar.draw(
+                img,
+                widget_position,
+                screen,
+                block.fill_colour,
+                block.stroke_colour,
+            ),
+            Widget::Calendar(grid) => {
+                grid.draw(img, widget_position, screen, font, block.fill_colour)
+            }
+            Widget::Timetable(timetable) => {
+                timetable.draw(img, widget_position, screen, font, block.fill_colour)
+            }
+        }
+        stacked_height += block_height + 2 * margin;
+    }
+}
+
+/// Resizes and crops `img` so that it covers `screen` exactly.
+fn cover_screen(mut img: DynamicImage, screen: Screen, crop: Crop) -> DynamicImage {
+    log::info!("Resizing image");
+    img = resize_to_contain_screen(img, screen);
+    log::info!("Cropping image");
+    crop_to_fit_screen_exactly(img, screen, crop)
+}
+
+/// Produces an image of exactly the size of `screen` from `img`, as configured by `framing`.
+pub(crate) fn fit_to_screen(img: DynamicImage, screen: Screen, framing: Framing) -> DynamicImage {
+    log::info!("Fitting image to screen ({:?})", framing.mode);
+    match framing.mode {
+        FitMode::Cover => cover_screen(img, screen, framing.crop),
+        FitMode::ContainBlur => {
+            let covered = cover_screen(img.clone(), screen, framing.crop);
+            contain_blur(&img, covered, screen)
+        }
+        FitMode::Letterbox => letterbox(&img, screen, framing.fill_colour),
+        FitMode::Tile => tile_unscaled(&img, screen),
+        FitMode::Centre => centre_unscaled(&img, screen, framing.fill_colour),
+        FitMode::Stretch => stretch(&img, screen),
+    }
+}
+
+/// A processed image without the layers that change with the time: the image fitted to its
+/// monitor, with the image name drawn.
+#[derive(Debug, Clone)]
+pub(crate) struct BaseImage {
+    img: RgbaImage,
+    image_name_height: i32,
+}
+
+/// Fits `img` to `monitor`, then draws the image name.
+pub(crate) fn process_base_image(
+    img: DynamicImage,
+    image_path: &str,
+    framing: Framing,
+    monitor: &Monitor,
+    font: &Font,
+) -> anyhow::Result<BaseImage> {
+    log::info!(
+        "Processing image {} for monitor {}",
+        image_path,
+        monitor.name
+    );
+    let screen = monitor.screen;
+    let mut img = fit_to_screen(img, screen, framing).to_rgba8();
+
     let image_name = PathBuf::from(image_path)
         .file_stem()
         .with_context(|| "Unable to get file stem of image")?
         .to_string_lossy()
         .to_string();
 
-    let image_name_font_scale = Scale::uniform(20.0);
+    let margin = screen.scale_px(12);
+    let inset_bottom = monitor.layout.bottom as i32;
+    let inset_right = monitor.layout.right as i32;
+    let image_name_font_scale = Scale::uniform(screen.scale(20.0));
     let image_name_text_size =
-        imageproc::drawing::text_size(image_name_font_scale, &font, &image_name);
+        imageproc::drawing::text_size(image_name_font_scale, font, &image_name);
     log::info!("Drawing image name");
     draw_text_with_border_mut(
         &mut img,
         Rgba([255, 255, 255, 255]),
-        SCREEN_WIDTH_PX as i32 - 12 - image_name_text_size.0,
-        SCREEN_HEIGHT_PX as i32 - 40 - 12 - image_name_text_size.1, // Height of taskbar is 40 px (Latte)
+        screen.width_px as i32 - inset_right - margin - image_name_text_size.0,
+        screen.height_px as i32 - inset_bottom - margin - image_name_text_size.1,
         image_name_font_scale,
-        &font,
+        font,
         &image_name,
         Rgba([0, 0, 0, 127]),
-        2,
+        screen.scale_outline_px(2),
     );
+    Ok(BaseImage {
+        img,
+        image_name_height: image_name_text_size.1,
+    })
+}
 
+/// The countdown blocks as of `now`, each with the name of the monitor it is drawn on, in
+/// stacking order. Calendars and holiday files are read once per render here, rather than once for
+/// each monitor.
+pub(crate) fn countdown_blocks(
+    config: &Config,
+    now: DateTime<Local>,
+) -> anyhow::Result<Vec<(String, CountdownBlock)>> {
+    let mut blocks = Vec::new();
+    let working_days = WorkingDays::from_config(&config.working_days)?;
     if let Some(c) = &config.countdown {
         log::info!("Processing countdown");
-        let today_string = generate_today_string(c)?;
-        let today_string_font_scale = Scale::uniform(20.0);
-        let today_string_text_size: (i32, i32) =
-            imageproc::drawing::text_size(today_string_font_scale, &font, &today_string);
-
-        let countdown_str = get_countdown_str(c)?;
-        let countdown_str_font_scale = Scale::uniform(200.0);
-        let countdown_str_text_size =
-            imageproc::drawing::text_size(countdown_str_font_scale, &font, &countdown_str);
-
-        let font_fill_colour = get_font_fill_colour(c)?;
-        let font_stroke_colour = get_font_stroke_colour(c)?;
-        log::info!("Drawing countdown");
-        draw_text_with_border_mut(
-            &mut img,
-            font_fill_colour,
-            12,
-            SCREEN_HEIGHT_PX as i32 - 40 - 12 - today_string_text_size.1, // Height of taskbar is 40 px (Latte)
-            today_string_font_scale,
-            &font,
-            &today_string,
-            font_stroke_colour,
-            2,
-        );
-        draw_text_with_border_mut(
+        let monitor_name = config.widget_monitor_name(&c.monitor);
+        blocks.push((monitor_name.clone(), term_block(c, &working_days, now)?));
+        blocks.extend(timetable_block(c, now)?.map(|block| (monitor_name, block)));
+    }
+    let monitor_name = config.widget_monitor_name(&config.countdowns.monitor);
+    let ramp = event_ramp(&config.countdowns.ramp).context("Invalid [countdowns] ramp")?;
+    let mut events = Vec::new();
+    for event in &config.countdowns.events {
+        events.push(CountdownEvent::from_config(event)?);
+    }
+    for calendar in &config.countdowns.calendars {
+        events.extend(next_calendar_event(calendar, now)?);
+    }
+    for event in &events {
+        let block = event_block(event, &ramp, &config.countdowns.format, &working_days, now)?;
+        blocks.extend(block.map(|block| (monitor_name.clone(), block)));
+    }
+    Ok(blocks)
+}
+
+/// Draws the `countdown_blocks` and overlay that are to be drawn on `monitor` over a copy of
+/// `base`.
+pub(crate) fn draw_time_layers(
+    base: &BaseImage,
+    monitor: &Monitor,
+    config: &Config,
+    font: &Font,
+    countdown_blocks: &[(String, CountdownBlock)],
+) -> anyhow::Result<RgbaImage> {
+    let screen = monitor.screen;
+    let mut img = base.img.clone();
+    let inset_top = monitor.layout.top as i32;
+    let inset_bottom = monitor.layout.bottom as i32;
+    let inset_left = monitor.layout.left as i32;
+    let inset_right = monitor.layout.right as i32;
+
+    let countdown_blocks: Vec<CountdownBlock> = countdown_blocks
+        .iter()
+        .filter(|(monitor_name, _)| *monitor_name == monitor.name)
+        .map(|(_, block)| block.clone())
+        .collect();
+    if !countdown_blocks.is_empty() {
+        log::info!("Drawing countdowns");
+        draw_countdown_blocks(
             &mut img,
-            font_fill_colour,
-            12,
-            SCREEN_HEIGHT_PX as i32
-                - 40
-                - 12
-                - today_string_text_size.1
-                - 12
-                - countdown_str_text_size.1, // Height of taskbar is 40 px (Latte)
-            countdown_str_font_scale,
-            &font,
-            &countdown_str,
-            font_stroke_colour,
-            6,
+            &countdown_blocks,
+            config.countdowns.corner,
+            monitor,
+            font,
+            base.image_name_height,
         );
     }
 
-    if let Some(c) = &config.overlay {
+    let overlay = config
+        .overlay
+        .as_ref()
+        .filter(|c| config.widget_monitor_name(&c.monitor) == monitor.name);
+    if let Some(c) = overlay {
         log::info!("Processing overlay");
         let pixel_sum = img.pixels().fold(0u64, |acc, e| {
             acc + e.0[0] as u64 + e.0[1] as u64 + e.0[2] as u64
@@ -223,16 +660,17 @@ pub(crate) fn process_image(
         log::info!("Preparing overlay");
         let mut t_image = RgbaImage::from_pixel(img.width(), img.height(), base_overlay_rgba);
         let (overlay_text_scale, overlay_text_size) =
-            calculate_overlay_text_scale_and_size(&font, &c.text)?;
+            calculate_overlay_text_scale_and_size(font, &c.text, monitor)?;
         imageproc::drawing::draw_text_mut(
             &mut t_image,
             Rgba([0; 4]),
-            (SCREEN_WIDTH_PX as i32 - overlay_text_size.0) / 2,
-            // shift upwards by 10, so that the distance from text to top of screen is (x + 20) and
-            // the distance from text to bottom of screen is (x + 40), where the header bar is 20 px // tall and Latte is 40 px tall.
-            (SCREEN_HEIGHT_PX as i32 - overlay_text_size.1) / 2 - 10,
+            // centred within the safe area
+            inset_left
+                + (screen.width_px as i32 - inset_left - inset_right - overlay_text_size.0) / 2,
+            inset_top
+                + (screen.height_px as i32 - inset_top - inset_bottom - overlay_text_size.1) / 2,
             overlay_text_scale,
-            &font,
+            font,
             &c.text,
         );
 
@@ -244,29 +682,29 @@ pub(crate) fn process_image(
         }
     }
 
-    log::info!("Saving processed image");
-    img.save_with_format(final_path, image::ImageFormat::Png)
-        .with_context(|| format!("Failed to save processed image to {}", final_path))?;
     log::info!("Done processing image");
-    Ok(())
+    Ok(img)
 }
 
 fn calculate_overlay_text_scale_and_size(
     font: &Font,
-    text: &String,
+    text: &str,
+    monitor: &Monitor,
 ) -> anyhow::Result<(Scale, (i32, i32))> {
-    let mut t_scale = Scale::uniform(SCREEN_HEIGHT_PX as f32);
+    let screen = monitor.screen;
+    let layout = monitor.layout;
+    let mut t_scale = Scale::uniform(screen.height_px as f32);
     let mut text_size_tup = text_size(t_scale, font, text);
     let text_size_bounds = (
-        SCREEN_WIDTH_PX as i32 - 24,
-        SCREEN_HEIGHT_PX as i32 - 40 - 20 - 24,
+        (screen.width_px - layout.left - layout.right) as i32 - screen.scale_px(TEXT_MARGIN_PX),
+        (screen.height_px - layout.top - layout.bottom) as i32 - screen.scale_px(TEXT_MARGIN_PX),
     );
 
     if text_size_tup.0 > text_size_bounds.0 {
         // Need scale down
         log::debug!("[calculate_overlay_text_scale_and_size] Scale down");
         let scale_factor: f32 = text_size_bounds.0 as f32 / text_size_tup.0 as f32;
-        t_scale = Scale::uniform(SCREEN_HEIGHT_PX as f32 * scale_factor);
+        t_scale = Scale::uniform(screen.height_px as f32 * scale_factor);
         text_size_tup = text_size(t_scale, font, text);
     } else if text_size_tup.1 > text_size_bounds.1 {
         // Won't happen if you don't scale up carelessly
@@ -279,13 +717,13 @@ fn calculate_overlay_text_scale_and_size(
             log::debug!("[calculate_overlay_text_scale_and_size] Scale up (Case 1)");
             // in terms of aspect ratio, text_size too tall; >> fit HEIGHT to text_size_bounds
             let scale_factor = text_size_bounds.1 as f32 / text_size_tup.1 as f32;
-            t_scale = Scale::uniform(SCREEN_HEIGHT_PX as f32 * scale_factor);
+            t_scale = Scale::uniform(screen.height_px as f32 * scale_factor);
             text_size_tup = text_size(t_scale, font, text);
         } else {
             log::debug!("[calculate_overlay_text_scale_and_size] Scale up (Case 2)");
             // in terms of aspect ratio, text_size too long; fit WIDTH to text_size_bounds
             let scale_factor = text_size_bounds.0 as f32 / text_size_tup.0 as f32;
-            t_scale = Scale::uniform(SCREEN_HEIGHT_PX as f32 * scale_factor);
+            t_scale = Scale::uniform(screen.height_px as f32 * scale_factor);
             text_size_tup = text_size(t_scale, font, text);
         }
     }
@@ -296,3 +734,45 @@ fn calculate_overlay_text_scale_and_size(
     );
     Ok((t_scale, text_size_tup))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_error(relative: &str) -> anyhow::Error {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(relative);
+        load_image(&path.to_string_lossy()).unwrap_err()
+    }
+
+    #[test]
+    fn overlay_text_fits_the_smallest_drawable_area() {
+        let font_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/DejaVuSans.ttf");
+        let font = Font::try_from_vec(std::fs::read(font_path).unwrap()).unwrap();
+        // 1 px of drawable area on each axis, once the margin is taken off
+        let monitor = Monitor {
+            name: String::from("test"),
+            screen: Screen {
+                width_px: 1920,
+                height_px: 1080,
+            },
+            x_px: 0,
+            y_px: 0,
+            layout: crate::config::ConfigLayout {
+                top: 0,
+                bottom: 1055,
+                left: 1000,
+                right: 895,
+            },
+        };
+        let (scale, (width, height)) =
+            calculate_overlay_text_scale_and_size(&font, "12 days", &monitor).unwrap();
+        assert!(scale.x > 0.0 && scale.y > 0.0, "{:?}", scale);
+        assert!(width <= 1 && height <= 1, "{}x{}", width, height);
+    }
+
+    #[test]
+    fn only_invalid_images_are_quarantined() {
+        assert!(is_read_error(&load_error("tests/fixtures/missing.png")));
+        assert!(!is_read_error(&load_error("tests/fixtures/DejaVuSans.ttf")));
+    }
+}
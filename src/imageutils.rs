@@ -1,7 +1,43 @@
+use std::{fs::File, io::BufReader};
+
 use image::{DynamicImage, Pixel, Rgba, RgbaImage};
 use rusttype::{Font, Scale};
 
+/// Reads the EXIF Orientation tag (1 to 8) of an image file. Images without EXIF metadata (or
+/// with unreadable metadata) are treated as having orientation 1, i.e. no transformation.
+pub fn read_exif_orientation(image_path: &str) -> u32 {
+    let orientation = File::open(image_path)
+        .ok()
+        .and_then(|file| {
+            exif::Reader::new()
+                .read_from_container(&mut BufReader::new(file))
+                .ok()
+        })
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1);
+    log::debug!("EXIF orientation of {} is {}", image_path, orientation);
+    orientation
+}
+
+/// Rotates and/or flips `img` so that it is displayed upright, given its EXIF orientation.
+pub fn apply_exif_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
 /// Courtesy of and inspired from https://github.com/image-rs/imageproc/issues/479#issuecomment-991778692
+#[allow(clippy::too_many_arguments)]
 pub fn draw_text_with_border_mut<'a>(
     canvas: &mut RgbaImage,
     color: Rgba<u8>,
@@ -35,3 +71,77 @@ pub fn draw_text_with_border_mut<'a>(
     }
     imageproc::drawing::draw_text_mut(canvas, color, x, y, scale, font, text);
 }
+
+/// Blends `colour` over the rectangle at `(x, y)` of size `(width, height)`, clipped to `canvas`.
+pub fn blend_rect_mut(
+    canvas: &mut RgbaImage,
+    (x, y): (i32, i32),
+    (width, height): (i32, i32),
+    colour: Rgba<u8>,
+) {
+    let x_range = x.max(0)..(x + width).min(canvas.width() as i32);
+    let y_range = y.max(0)..(y + height).min(canvas.height() as i32);
+    for py in y_range {
+        for px in x_range.clone() {
+            canvas.get_pixel_mut(px as u32, py as u32).blend(&colour);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    /// A 3x2 image as stored in the file, with its top-left corner red, top-right green and
+    /// bottom-left blue.
+    fn stored_image() -> DynamicImage {
+        let mut img = RgbaImage::from_pixel(3, 2, WHITE);
+        img.put_pixel(0, 0, RED);
+        img.put_pixel(2, 0, GREEN);
+        img.put_pixel(0, 1, BLUE);
+        DynamicImage::ImageRgba8(img)
+    }
+
+    #[test]
+    fn orientations_display_the_image_upright() {
+        // Orientation, then where the red, green and blue corners are displayed
+        let cases = [
+            (1, (3, 2), [(0, 0), (2, 0), (0, 1)]),
+            (2, (3, 2), [(2, 0), (0, 0), (2, 1)]),
+            (3, (3, 2), [(2, 1), (0, 1), (2, 0)]),
+            (4, (3, 2), [(0, 1), (2, 1), (0, 0)]),
+            // Transposed: the stored rows are displayed as columns
+            (5, (2, 3), [(0, 0), (0, 2), (1, 0)]),
+            // Stored rotated 90° anticlockwise, so displayed rotated 90° clockwise
+            (6, (2, 3), [(1, 0), (1, 2), (0, 0)]),
+            // Transversed: the stored top-left corner is displayed bottom-right
+            (7, (2, 3), [(1, 2), (1, 0), (0, 2)]),
+            // Stored rotated 90° clockwise, so displayed rotated 90° anticlockwise
+            (8, (2, 3), [(0, 2), (0, 0), (1, 2)]),
+        ];
+        for (orientation, (width, height), corners) in cases {
+            let img = apply_exif_orientation(stored_image(), orientation).to_rgba8();
+            assert_eq!(img.dimensions(), (width, height), "{}", orientation);
+            for ((x, y), colour) in corners.into_iter().zip([RED, GREEN, BLUE]) {
+                assert_eq!(*img.get_pixel(x, y), colour, "{}", orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn files_without_exif_are_upright() {
+        let path = std::env::temp_dir().join(format!(
+            "random-background-no-exif-{}.png",
+            std::process::id()
+        ));
+        stored_image().save(&path).unwrap();
+        assert_eq!(read_exif_orientation(&path.to_string_lossy()), 1);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_exif_orientation(&path.to_string_lossy()), 1);
+    }
+}
@@ -1,18 +1,43 @@
-use anyhow::{anyhow, Context};
-use clap::{command, Parser};
-use image::{Rgb, RgbImage};
-use std::path::PathBuf;
+use anyhow::{bail, Context};
+use chrono::{DateTime, Local};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
 
 use crate::{
     config::Config,
-    imageops::{choose_one_image, process_image},
+    daemon::run_daemon,
+    dateutils::parse_local_datetime,
+    imageops::{parse_resolution, Screen, Selection},
+    ratings::{parse_weight, Ratings},
+    refresh::refresh_loop,
+    render::render_wallpapers,
+    scan::image_key,
+    setter::set_wallpapers,
 };
 
+mod calendar;
+mod colour;
 mod config;
 mod countdown;
+mod daemon;
 mod dateutils;
+mod fit;
+#[cfg(test)]
+mod golden_tests;
+mod history;
 mod imageops;
 mod imageutils;
+mod quarantine;
+mod ratings;
+mod refresh;
+mod render;
+mod scan;
+mod setter;
+mod shuffle_bag;
+mod smartcrop;
+mod template;
+mod widgets;
+mod workdays;
 
 #[derive(Parser, Debug)]
 #[command(name = "Random Background", author, version, about, long_about = None)]
@@ -20,6 +45,56 @@ struct Args {
     /// Path to directory containing the images
     #[arg(short, long)]
     dir: String,
+
+    /// Resolution of the generated background, e.g. 2560x1440 (overrides config.toml)
+    #[arg(short, long, value_parser = parse_resolution)]
+    resolution: Option<Screen>,
+
+    /// Render the countdown as if it were this local date or date-time, e.g. 2024-05-20 or
+    /// 2024-05-20T09:00:00 (default now)
+    #[arg(long, value_parser = parse_local_datetime)]
+    at: Option<DateTime<Local>>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Set the selection weight of an image (used when selection mode is 'weighted')
+    Rate {
+        /// Path to the image, or its path relative to the image directory
+        image: String,
+        /// A non-negative number, or one of favourite, normal, rarely
+        #[arg(value_parser = parse_weight)]
+        weight: f64,
+    },
+    /// Render the background without setting it, e.g. for scripts or headless machines
+    Render {
+        /// Where to save the PNG (default <DIR>/Working/current.png); with per-monitor output,
+        /// -<MONITOR> is appended to the file name
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Keep running, and set the background again every hour (or INTERVAL minutes)
+    ///
+    /// The images are chosen once a day, and in between only the countdowns and overlay are
+    /// redrawn over them.
+    Refresh {
+        /// Minutes between refreshes, from 1 to 1440
+        #[arg(
+            short,
+            long,
+            default_value_t = 60,
+            value_parser = clap::value_parser!(u32).range(1..=1440)
+        )]
+        interval: u32,
+    },
+    /// Keep running, and set the background on the [schedule] in config.toml
+    ///
+    /// Runs missed while suspended are caught up on. SIGHUP reloads config.toml, and SIGTERM stops
+    /// the daemon.
+    Daemon,
 }
 
 fn ensure_working_dir_exists(parent_dir: &str) -> anyhow::Result<()> {
@@ -27,43 +102,75 @@ fn ensure_working_dir_exists(parent_dir: &str) -> anyhow::Result<()> {
     std::fs::create_dir_all(&working_directory_path).with_context(|| {
         format!(
             "Failed to ensure {} directory exists",
-            working_directory_path.to_string_lossy().to_string()
+            working_directory_path.to_string_lossy()
         )
     })?;
     Ok(())
 }
 
-fn ensure_blank_background_exists(blank_wallpaper_path: &str) -> anyhow::Result<()> {
-    RgbImage::from_pixel(1, 1, Rgb([0, 0, 0]))
-        .save(&blank_wallpaper_path)
-        .with_context(|| format!("Failed to save blank wallpaper to {}", blank_wallpaper_path))?;
+fn rate_image(parent_dir: &str, image: &str, weight: f64) -> anyhow::Result<()> {
+    // Accept both paths (e.g. from shell completion) and names relative to the image directory
+    let image_name = image_key(parent_dir, Path::new(image));
+    if !Path::new(parent_dir).join(&image_name).is_file() {
+        log::warn!("{} is not an image in {}", image_name, parent_dir);
+    }
+    let mut ratings = Ratings::read_from_dir(parent_dir)?;
+    ratings.set_weight(&image_name, weight);
+    ratings.save()?;
+    log::info!("Set weight of {} to {}", image_name, weight);
     Ok(())
 }
 
+/// Reads config.toml, with the options that override it applied.
+fn read_config(args: &Args) -> anyhow::Result<Config> {
+    let mut config = Config::read_from_dir(&args.dir)?;
+    if let Some(screen) = args.resolution {
+        if !config.display.monitors.is_empty() {
+            log::warn!("--resolution is ignored as monitors are declared in config.toml");
+        }
+        config.general.screen_width_px = screen.width_px;
+        config.general.screen_height_px = screen.height_px;
+    }
+    Ok(config)
+}
+
 fn main() -> anyhow::Result<()> {
     env_logger::init();
     let args: Args = Args::parse();
     ensure_working_dir_exists(&args.dir)?;
-    let config = Config::read_from_dir(&args.dir)?;
-
-    let blank_wallpaper_path: PathBuf = [&args.dir, "Working", "blank.png"].iter().collect();
-    let blank_wallpaper_path = blank_wallpaper_path.to_string_lossy().to_string();
-    ensure_blank_background_exists(&blank_wallpaper_path)?;
-
-    let chosen_img_path = choose_one_image(&args.dir)?;
-    let final_wallpaper_path: PathBuf = [&args.dir, "Working", "current.png"].iter().collect();
-    let final_wallpaper_path = final_wallpaper_path.to_string_lossy().to_string();
+    if let Some(Command::Rate { image, weight }) = &args.command {
+        return rate_image(&args.dir, image, *weight);
+    }
+    if let Some(Command::Daemon) = &args.command {
+        if args.at.is_some() {
+            bail!("--at can't be used with daemon, which follows the clock");
+        }
+        return run_daemon(&args.dir, || read_config(&args));
+    }
+    let config = read_config(&args)?;
 
-    process_image(&chosen_img_path, &final_wallpaper_path, &config)?;
+    if let Some(Command::Refresh { interval }) = &args.command {
+        if args.at.is_some() {
+            bail!("--at can't be used with refresh, which follows the clock");
+        }
+        return refresh_loop(&args.dir, &config, *interval);
+    }
 
-    wallpaper::set_mode(wallpaper::Mode::Crop)
-        .map_err(|e| anyhow!("Failed to set wallpaper mode: {}", e))?;
+    let now = args.at.unwrap_or_else(Local::now);
 
-    wallpaper::set_from_path(&blank_wallpaper_path)
-        .map_err(|e| anyhow!("Failed to set wallpaper: {}", e))?;
+    if let Some(Command::Render { output }) = &args.command {
+        render_wallpapers(
+            &args.dir,
+            &config,
+            Selection::DryRun,
+            output.as_deref(),
+            now,
+        )?;
+        return Ok(());
+    }
 
-    wallpaper::set_from_path(&final_wallpaper_path)
-        .map_err(|e| anyhow!("Failed to set wallpaper: {}", e))?;
+    let final_wallpapers = render_wallpapers(&args.dir, &config, Selection::Record, None, now)?;
+    set_wallpapers(&args.dir, &config.setter, &final_wallpapers)?;
 
     Ok(())
 }
@@ -0,0 +1,87 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct QuarantineList {
+    /// Reason each image was quarantined, keyed by image name
+    #[serde(default)]
+    images: BTreeMap<String, String>,
+}
+
+/// Images that failed to decode and are never chosen again, persisted in
+/// `<DIR>/Working/quarantine.toml`. Removing an entry from that file releases the image.
+#[derive(Debug)]
+pub(crate) struct Quarantine {
+    parent_dir: String,
+    path: PathBuf,
+    list: QuarantineList,
+}
+
+impl Quarantine {
+    pub(crate) fn read_from_dir(parent_dir: &str) -> anyhow::Result<Self> {
+        let path: PathBuf = [parent_dir, "Working", "quarantine.toml"].iter().collect();
+        let list = if path.exists() {
+            let toml_str = std::fs::read_to_string(&path).with_context(|| {
+                format!(
+                    "Failed to read quarantine list from {}",
+                    path.to_string_lossy()
+                )
+            })?;
+            toml::from_str(&toml_str).with_context(|| {
+                format!("Please fix the TOML file at {}", path.to_string_lossy())
+            })?
+        } else {
+            QuarantineList::default()
+        };
+        Ok(Self {
+            parent_dir: parent_dir.to_string(),
+            path,
+            list,
+        })
+    }
+
+    pub(crate) fn contains(&self, image_name: &str) -> bool {
+        self.list.images.contains_key(image_name)
+    }
+
+    pub(crate) fn add(&mut self, image_name: &str, reason: String) {
+        self.list.images.insert(image_name.to_string(), reason);
+    }
+
+    /// Moves the image into `<DIR>/Working/Quarantine`, keeping its path relative to `<DIR>`.
+    pub(crate) fn move_to_folder(&self, image_name: &str) -> anyhow::Result<()> {
+        let source_path: PathBuf = [&self.parent_dir, image_name].iter().collect();
+        let target_path: PathBuf = [&self.parent_dir, "Working", "Quarantine", image_name]
+            .iter()
+            .collect();
+        if let Some(target_dir) = target_path.parent() {
+            std::fs::create_dir_all(target_dir).with_context(|| {
+                format!(
+                    "Failed to ensure {} directory exists",
+                    target_dir.to_string_lossy()
+                )
+            })?;
+        }
+        std::fs::rename(&source_path, &target_path).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                source_path.to_string_lossy(),
+                target_path.to_string_lossy()
+            )
+        })?;
+        log::info!("Moved {} to {}", image_name, target_path.to_string_lossy());
+        Ok(())
+    }
+
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let toml_str = toml::to_string(&self.list)?;
+        std::fs::write(&self.path, toml_str).with_context(|| {
+            format!(
+                "Failed to save quarantine list to {}",
+                self.path.to_string_lossy()
+            )
+        })
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+pub(crate) const FAVOURITE_WEIGHT: f64 = 4.0;
+pub(crate) const NORMAL_WEIGHT: f64 = 1.0;
+pub(crate) const RARELY_WEIGHT: f64 = 0.25;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RatingsManifest {
+    /// Weight of images not listed in `weights`
+    #[serde(default = "RatingsManifest::default_default_weight")]
+    default_weight: f64,
+    /// Weight of each image, keyed by image name
+    #[serde(default)]
+    weights: BTreeMap<String, f64>,
+}
+
+impl RatingsManifest {
+    fn default_default_weight() -> f64 {
+        NORMAL_WEIGHT
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if !is_valid_weight(self.default_weight) {
+            bail!(
+                "default_weight must be a non-negative number, got {}",
+                self.default_weight
+            );
+        }
+        for (image_name, weight) in &self.weights {
+            if !is_valid_weight(*weight) {
+                bail!(
+                    "Weight of {} must be a non-negative number, got {}",
+                    image_name,
+                    weight
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for RatingsManifest {
+    fn default() -> Self {
+        Self {
+            default_weight: Self::default_default_weight(),
+            weights: BTreeMap::new(),
+        }
+    }
+}
+
+/// Per-image selection weights, persisted in `<DIR>/Working/ratings.toml`.
+#[derive(Debug)]
+pub(crate) struct Ratings {
+    path: PathBuf,
+    manifest: RatingsManifest,
+}
+
+impl Ratings {
+    pub(crate) fn read_from_dir(parent_dir: &str) -> anyhow::Result<Self> {
+        let path: PathBuf = [parent_dir, "Working", "ratings.toml"].iter().collect();
+        let manifest = if path.exists() {
+            let toml_str = std::fs::read_to_string(&path).with_context(|| {
+                format!("Failed to read ratings from {}", path.to_string_lossy())
+            })?;
+            let manifest: RatingsManifest = toml::from_str(&toml_str).with_context(|| {
+                format!("Please fix the TOML file at {}", path.to_string_lossy())
+            })?;
+            manifest.validate().with_context(|| {
+                format!("Please fix the TOML file at {}", path.to_string_lossy())
+            })?;
+            manifest
+        } else {
+            RatingsManifest::default()
+        };
+        Ok(Self { path, manifest })
+    }
+
+    pub(crate) fn weight_of(&self, image_name: &str) -> f64 {
+        self.manifest
+            .weights
+            .get(image_name)
+            .copied()
+            .unwrap_or(self.manifest.default_weight)
+    }
+
+    pub(crate) fn set_weight(&mut self, image_name: &str, weight: f64) {
+        self.manifest.weights.insert(image_name.to_string(), weight);
+    }
+
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let toml_str = toml::to_string(&self.manifest)?;
+        std::fs::write(&self.path, toml_str)
+            .with_context(|| format!("Failed to save ratings to {}", self.path.to_string_lossy()))
+    }
+}
+
+fn is_valid_weight(weight: f64) -> bool {
+    weight >= 0.0 && weight.is_finite()
+}
+
+/// Parses a weight given on the command line: either a non-negative number, or one of
+/// `favourite`, `normal` and `rarely`.
+pub(crate) fn parse_weight(s: &str) -> anyhow::Result<f64> {
+    let weight = match s {
+        "favourite" | "favorite" => FAVOURITE_WEIGHT,
+        "normal" => NORMAL_WEIGHT,
+        "rarely" => RARELY_WEIGHT,
+        _ => s.parse().with_context(|| {
+            format!(
+                "{} is neither a number nor one of favourite, normal, rarely",
+                s
+            )
+        })?,
+    };
+    if !is_valid_weight(weight) {
+        bail!("Weight must be a non-negative number, got {}", s);
+    }
+    Ok(weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_weights_are_rejected_on_load() {
+        let dir =
+            std::env::temp_dir().join(format!("random-background-ratings-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Working")).unwrap();
+        let dir_path = dir.to_string_lossy();
+        for (toml, valid) in [
+            ("default_weight = 0.0\n[weights]\n'a.png' = 4.0\n", true),
+            ("default_weight = -1.0\n", false),
+            ("default_weight = inf\n", false),
+            ("[weights]\n'a.png' = nan\n", false),
+            ("[weights]\n'a.png' = -0.5\n", false),
+        ] {
+            std::fs::write(dir.join("Working").join("ratings.toml"), toml).unwrap();
+            match Ratings::read_from_dir(&dir_path) {
+                Ok(_) => assert!(valid, "{}", toml),
+                Err(e) => {
+                    assert!(!valid, "{}: {:#}", toml, e);
+                    assert!(format!("{:#}", e).contains("ratings.toml"), "{:#}", e);
+                }
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Local, NaiveDate, Timelike};
+use rusttype::Font;
+
+use crate::{
+    config::Config,
+    imageops::{load_font, BaseImage, Selection},
+    render::{finish_wallpapers, render_base_images},
+    setter::set_wallpapers,
+};
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Time until the next multiple of `interval_minutes` since midnight, or until midnight if that
+/// comes first, so that the renders line up with the clock (e.g. on the hour).
+fn until_next_tick(now: DateTime<Local>, interval_minutes: u32) -> Duration {
+    let interval = interval_minutes * 60;
+    let elapsed = now.num_seconds_from_midnight();
+    let next = ((elapsed / interval + 1) * interval).min(SECONDS_PER_DAY);
+    Duration::from_secs((next - elapsed) as u64).saturating_sub(Duration::from_nanos(
+        now.nanosecond().min(999_999_999) as u64,
+    ))
+}
+
+/// Whether the base images rendered for `old` look the same under `new`, so that they can be
+/// kept when the config is reloaded.
+fn same_base_images(old: &Config, new: &Config) -> bool {
+    old.monitors() == new.monitors()
+        && old.display.panorama == new.display.panorama
+        && old.fit == new.fit
+        && old.crop == new.crop
+        && old.general.ttf_font_path == new.general.ttf_font_path
+}
+
+/// Renders and sets the wallpapers, choosing, decoding and fitting new images once a day, and
+/// otherwise only redrawing the countdowns and overlay over the day's images.
+pub(crate) struct Refresher<'a> {
+    dir_path: &'a str,
+    font: Font<'static>,
+    base_images: Option<(NaiveDate, Vec<BaseImage>)>,
+}
+
+impl<'a> Refresher<'a> {
+    pub(crate) fn new(dir_path: &'a str, config: &Config) -> anyhow::Result<Self> {
+        Ok(Refresher {
+            dir_path,
+            font: load_font(config)?,
+            base_images: None,
+        })
+    }
+
+    /// Switches from the `old` config to the `new` one. The day's images are kept unless the
+    /// monitors, fit, crop or font changed, so that a reload doesn't choose new images.
+    pub(crate) fn reload(&mut self, old: &Config, new: &Config) -> anyhow::Result<()> {
+        self.font = load_font(new)?;
+        if !same_base_images(old, new) {
+            log::info!("The monitors, fit, crop or font changed, so new images will be chosen");
+            self.base_images = None;
+        }
+        Ok(())
+    }
+
+    /// Renders and sets the wallpapers as of `now`. Failures are logged rather than returned, as
+    /// they (e.g. of the setter) may well be temporary, and the next refresh may succeed.
+    pub(crate) fn refresh(&mut self, config: &Config, now: DateTime<Local>) {
+        if let Err(e) = self.try_refresh(config, now) {
+            log::error!("Failed to refresh wallpapers: {:#}", e);
+        }
+    }
+
+    fn try_refresh(&mut self, config: &Config, now: DateTime<Local>) -> anyhow::Result<()> {
+        let base_images = match &mut self.base_images {
+            Some((date, images)) if *date == now.date_naive() => images,
+            _ => {
+                log::info!("Choosing new images for {}", now.date_naive());
+                let images =
+                    render_base_images(self.dir_path, config, &self.font, Selection::Record)?;
+                &self.base_images.insert((now.date_naive(), images)).1
+            }
+        };
+        let wallpapers =
+            finish_wallpapers(self.dir_path, config, &self.font, base_images, None, now)?;
+        set_wallpapers(self.dir_path, &config.setter, &wallpapers)
+            .context("Failed to set wallpapers")
+    }
+}
+
+/// Renders and sets the wallpapers every `interval_minutes`.
+pub(crate) fn refresh_loop(
+    dir_path: &str,
+    config: &Config,
+    interval_minutes: u32,
+) -> anyhow::Result<()> {
+    let mut refresher = Refresher::new(dir_path, config)?;
+    loop {
+        refresher.refresh(config, Local::now());
+        let sleep = until_next_tick(Local::now(), interval_minutes);
+        log::info!("Next refresh in {}s", sleep.as_secs());
+        std::thread::sleep(sleep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dateutils::parse_local_datetime;
+
+    fn until(now: &str, interval_minutes: u32) -> u64 {
+        until_next_tick(parse_local_datetime(now).unwrap(), interval_minutes).as_secs()
+    }
+
+    #[test]
+    fn ticks_line_up_with_the_clock() {
+        assert_eq!(until("2024-06-12T09:00:00", 60), 3600);
+        assert_eq!(until("2024-06-12T09:59:30", 60), 30);
+        assert_eq!(until("2024-06-12T09:07:00", 15), 8 * 60);
+    }
+
+    #[test]
+    fn keeps_base_images_unless_they_would_change() {
+        let config = |toml: &str| -> Config {
+            toml::from_str(&format!("[general]\nttf_font_path = 'font.ttf'\n{}", toml)).unwrap()
+        };
+        let old = config("");
+        assert!(same_base_images(
+            &old,
+            &config("[overlay]\ntext = 'Revise'\n")
+        ));
+        assert!(same_base_images(
+            &old,
+            &config("[selection]\nmode = 'shuffle_bag'\n")
+        ));
+        assert!(!same_base_images(
+            &old,
+            &config("[fit]\nmode = 'letterbox'\n")
+        ));
+        assert!(!same_base_images(&old, &config("[layout]\ntop = 0\n")));
+        let mut other_font = config("");
+        other_font.general.ttf_font_path = String::from("other.ttf");
+        assert!(!same_base_images(&old, &other_font));
+    }
+
+    #[test]
+    fn ticks_at_midnight() {
+        // 23:00 + 7h would be tomorrow, but a new day needs new images
+        assert_eq!(until("2024-06-12T23:00:00", 7 * 60), 3600);
+    }
+}
@@ -0,0 +1,267 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use image::{GenericImage, Rgba, RgbaImage};
+use rusttype::Font;
+
+use crate::{
+    config::{Config, ConfigLayout, DisplayOutput, FitMode},
+    fit::{Framing, FramingResolver},
+    imageops::{
+        choose_and_load_image, countdown_blocks, draw_time_layers, fit_to_screen, load_font,
+        process_base_image, BaseImage, Screen, Selection,
+    },
+    scan::image_key,
+    smartcrop::FocalPoints,
+};
+
+/// Name of the monitor used when no monitors are declared in `[display]`.
+pub(crate) const DEFAULT_MONITOR_NAME: &str = "default";
+
+/// One physical monitor: a region of the spanned canvas.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Monitor {
+    pub(crate) name: String,
+    pub(crate) screen: Screen,
+    pub(crate) x_px: u32,
+    pub(crate) y_px: u32,
+    pub(crate) layout: ConfigLayout,
+}
+
+/// Bounding box of all monitors, i.e. the size of the spanned canvas.
+fn canvas_screen(monitors: &[Monitor]) -> Screen {
+    Screen {
+        width_px: monitors
+            .iter()
+            .map(|monitor| monitor.x_px + monitor.screen.width_px)
+            .max()
+            .unwrap_or(0),
+        height_px: monitors
+            .iter()
+            .map(|monitor| monitor.y_px + monitor.screen.height_px)
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+/// A saved wallpaper image, and the monitor it is for (`None` if it spans all monitors).
+#[derive(Debug, Clone)]
+pub(crate) struct RenderedWallpaper {
+    pub(crate) monitor: Option<String>,
+    pub(crate) path: String,
+}
+
+/// Where a rendered wallpaper is saved: `<DIR>/Working/current[-<MONITOR>].png` by default, or
+/// `output` (with `-<MONITOR>` appended to the file stem for per-monitor output).
+fn wallpaper_path(dir_path: &str, output: Option<&str>, monitor_name: Option<&str>) -> String {
+    let path: PathBuf = match (output, monitor_name) {
+        (None, None) => [dir_path, "Working", "current.png"].iter().collect(),
+        (None, Some(name)) => [dir_path, "Working", &format!("current-{}.png", name)]
+            .iter()
+            .collect(),
+        (Some(output), None) => PathBuf::from(output),
+        (Some(output), Some(name)) => {
+            let output = Path::new(output);
+            let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+            let file_name = match output.extension() {
+                Some(extension) => {
+                    format!("{}-{}.{}", stem, name, extension.to_string_lossy())
+                }
+                None => format!("{}-{}", stem, name),
+            };
+            output.with_file_name(file_name)
+        }
+    };
+    path.to_string_lossy().to_string()
+}
+
+fn save_png(img: &RgbaImage, path: &str) -> anyhow::Result<()> {
+    log::info!("Saving processed image to {}", path);
+    img.save_with_format(path, image::ImageFormat::Png)
+        .with_context(|| format!("Failed to save processed image to {}", path))
+}
+
+/// Chooses an image for every monitor (or one image spanning them all, for a panorama), and
+/// processes the layers that don't change with the time.
+pub(crate) fn render_base_images(
+    dir_path: &str,
+    config: &Config,
+    font: &Font,
+    selection: Selection,
+) -> anyhow::Result<Vec<BaseImage>> {
+    let monitors = config.monitors();
+    let focal_points = FocalPoints::read_from_dir(dir_path)?;
+    let framing_resolver = FramingResolver::new(&config.fit, config.crop.strategy, &focal_points)
+        .context("Please fix the [fit] overrides in config.toml")?;
+    let framing_for = |chosen_img_path: &str| {
+        framing_resolver.framing_for(&image_key(dir_path, Path::new(chosen_img_path)))
+    };
+
+    if config.display.panorama {
+        // One image fitted to the whole canvas; each monitor shows its own region of it
+        let (chosen_img_path, img) = choose_and_load_image(dir_path, config, selection)?;
+        let framing = framing_for(&chosen_img_path);
+        let img = fit_to_screen(img, canvas_screen(&monitors), framing);
+        // Each region already fits its monitor exactly, so covering it is a no-op
+        let framing = Framing {
+            mode: FitMode::Cover,
+            ..framing
+        };
+        monitors
+            .iter()
+            .map(|monitor| {
+                let region = img.crop_imm(
+                    monitor.x_px,
+                    monitor.y_px,
+                    monitor.screen.width_px,
+                    monitor.screen.height_px,
+                );
+                process_base_image(region, &chosen_img_path, framing, monitor, font)
+            })
+            .collect()
+    } else {
+        monitors
+            .iter()
+            .map(|monitor| {
+                let (chosen_img_path, img) = choose_and_load_image(dir_path, config, selection)?;
+                let framing = framing_for(&chosen_img_path);
+                process_base_image(img, &chosen_img_path, framing, monitor, font)
+            })
+            .collect()
+    }
+}
+
+/// Draws the countdowns and overlay as of `now` over the `base_images` (one per monitor), then
+/// saves them as configured by `[display] output`, into `<DIR>/Working` unless an `output` path is
+/// given.
+pub(crate) fn finish_wallpapers(
+    dir_path: &str,
+    config: &Config,
+    font: &Font,
+    base_images: &[BaseImage],
+    output: Option<&str>,
+    now: DateTime<Local>,
+) -> anyhow::Result<Vec<RenderedWallpaper>> {
+    let monitors = config.monitors();
+    let countdown_blocks = countdown_blocks(config, now)?;
+    let rendered = monitors
+        .iter()
+        .zip(base_images)
+        .map(|(monitor, base)| draw_time_layers(base, monitor, config, font, &countdown_blocks))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    match config.display.output {
+        DisplayOutput::Spanned => {
+            let canvas_screen = canvas_screen(&monitors);
+            let mut canvas = RgbaImage::from_pixel(
+                canvas_screen.width_px,
+                canvas_screen.height_px,
+                Rgba([0, 0, 0, 255]),
+            );
+            for (monitor, img) in monitors.iter().zip(rendered.iter()) {
+                canvas.copy_from(img, monitor.x_px, monitor.y_px)?;
+            }
+            let final_path = wallpaper_path(dir_path, output, None);
+            save_png(&canvas, &final_path)?;
+            Ok(vec![RenderedWallpaper {
+                monitor: None,
+                path: final_path,
+            }])
+        }
+        DisplayOutput::PerMonitor => monitors
+            .iter()
+            .zip(rendered.iter())
+            .map(|(monitor, img)| {
+                let final_path = wallpaper_path(dir_path, output, Some(&monitor.name));
+                save_png(img, &final_path)?;
+                Ok(RenderedWallpaper {
+                    monitor: Some(monitor.name.clone()),
+                    path: final_path,
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Chooses and processes images for every monitor, then saves them as configured by
+/// `[display] output`, into `<DIR>/Working` unless an `output` path is given.
+pub(crate) fn render_wallpapers(
+    dir_path: &str,
+    config: &Config,
+    selection: Selection,
+    output: Option<&str>,
+    now: DateTime<Local>,
+) -> anyhow::Result<Vec<RenderedWallpaper>> {
+    let font = load_font(config)?;
+    let base_images = render_base_images(dir_path, config, &font, selection)?;
+    finish_wallpapers(dir_path, config, &font, &base_images, output, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory with a valid image, an image that fails to decode, and a config.toml.
+    fn image_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("random-background-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("Working")).unwrap();
+        RgbaImage::from_pixel(32, 18, Rgba([40, 60, 90, 255]))
+            .save(dir.join("good.png"))
+            .unwrap();
+        std::fs::write(dir.join("bad.png"), "not a PNG").unwrap();
+        let font_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/DejaVuSans.ttf");
+        std::fs::write(
+            dir.join("Working").join("config.toml"),
+            format!(
+                "[general]\n\
+                 ttf_font_path = '{}'\n\
+                 screen_width_px = 192\n\
+                 screen_height_px = 108\n\
+                 [selection]\n\
+                 mode = 'shuffle_bag'\n",
+                font_path.to_string_lossy()
+            ),
+        )
+        .unwrap();
+        dir
+    }
+
+    fn selection_state(dir: &Path) -> Vec<Option<String>> {
+        ["history.txt", "shuffle_bag.toml", "quarantine.toml"]
+            .iter()
+            .map(|file| std::fs::read_to_string(dir.join("Working").join(file)).ok())
+            .collect()
+    }
+
+    #[test]
+    fn dry_run_leaves_selection_state_unchanged() {
+        let dir = image_dir("dry-run");
+        let dir_path = dir.to_string_lossy();
+        std::fs::write(dir.join("Working").join("history.txt"), "good.png\n").unwrap();
+        // The image that fails to decode comes out of the bag first
+        std::fs::write(
+            dir.join("Working").join("shuffle_bag.toml"),
+            "remaining = ['bad.png', 'good.png']\nshown = []\n",
+        )
+        .unwrap();
+        let config = Config::read_from_dir(&dir_path).unwrap();
+        let before = selection_state(&dir);
+
+        let (chosen, _) =
+            crate::imageops::choose_and_load_image(&dir_path, &config, Selection::DryRun).unwrap();
+        assert!(chosen.ends_with("good.png"), "{}", chosen);
+        render_wallpapers(&dir_path, &config, Selection::DryRun, None, Local::now()).unwrap();
+        assert_eq!(selection_state(&dir), before);
+        assert!(!dir.join("Working").join("quarantine.toml").exists());
+
+        render_wallpapers(&dir_path, &config, Selection::Record, None, Local::now()).unwrap();
+        assert_ne!(selection_state(&dir), before);
+        let quarantine =
+            std::fs::read_to_string(dir.join("Working").join("quarantine.toml")).unwrap();
+        assert!(quarantine.contains("bad.png"), "{}", quarantine);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::config::ConfigScan;
+
+/// File extensions (lowercase) of the formats the `image` crate can decode with its default
+/// features.
+pub(crate) const DECODABLE_EXTENSIONS: &[&str] = &[
+    "bmp", "dds", "exr", "ff", "gif", "hdr", "ico", "jpeg", "jpg", "pam", "pbm", "pgm", "png",
+    "pnm", "ppm", "qoi", "tga", "tif", "tiff", "webp",
+];
+
+/// Parses a glob pattern matched against image keys. `*` doesn't match `/`, so `'a/*.jpg'` only
+/// matches images directly in `a`; `**` matches any number of directories.
+pub(crate) fn glob(pattern: &str) -> anyhow::Result<Glob> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid glob pattern {}", pattern))
+}
+
+fn build_glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(glob(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Name under which an image is known in the `Working` directory (history, ratings, etc.): its
+/// path relative to `parent_dir`, with `/` as separator.
+pub(crate) fn image_key(parent_dir: &str, path: &Path) -> String {
+    let relative_path = path.strip_prefix(parent_dir).unwrap_or(path);
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Lists the candidate images in `parent_dir` according to the `[scan]` configuration.
+/// The `Working` directory is always skipped.
+pub(crate) fn find_images(parent_dir: &str, config: &ConfigScan) -> anyhow::Result<Vec<PathBuf>> {
+    let include = build_glob_set(&config.include)?;
+    let exclude = build_glob_set(&config.exclude)?;
+    let extensions: Vec<String> = config
+        .extensions
+        .iter()
+        .map(|extension| extension.trim_start_matches('.').to_lowercase())
+        .collect();
+    let working_directory_path: PathBuf = [parent_dir, "Working"].iter().collect();
+
+    let mut image_paths = Vec::new();
+    let mut pending_dirs = vec![PathBuf::from(parent_dir)];
+    while let Some(dir_path) = pending_dirs.pop() {
+        let entries = std::fs::read_dir(&dir_path)
+            .with_context(|| format!("Directory {} does not exist", dir_path.display()))?;
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            // file_type() doesn't follow symlinks, so symlinked directories are never descended
+            // into (avoiding cycles); symlinked files are still picked up via metadata() below.
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                if config.recursive && path != working_directory_path {
+                    pending_dirs.push(path);
+                }
+                continue;
+            }
+            if !path.metadata().is_ok_and(|metadata| metadata.is_file()) {
+                continue;
+            }
+            let has_allowed_extension = path.extension().is_some_and(|extension| {
+                extensions.contains(&extension.to_string_lossy().to_lowercase())
+            });
+            if !has_allowed_extension {
+                log::debug!("Skipping {} (extension not allowed)", path.display());
+                continue;
+            }
+            let key = image_key(parent_dir, &path);
+            if (!config.include.is_empty() && !include.is_match(&key)) || exclude.is_match(&key) {
+                log::debug!("Skipping {} (include/exclude rules)", key);
+                continue;
+            }
+            image_paths.push(path);
+        }
+    }
+    // Directory listing order is unspecified; sort so that results are reproducible
+    image_paths.sort();
+    Ok(image_paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(dir: &Path, scan_toml: &str) -> Vec<String> {
+        let config: ConfigScan = toml::from_str(scan_toml).unwrap();
+        find_images(&dir.to_string_lossy(), &config)
+            .unwrap()
+            .iter()
+            .map(|path| image_key(&dir.to_string_lossy(), path))
+            .collect()
+    }
+
+    #[test]
+    fn stars_do_not_match_nested_folders() {
+        let dir =
+            std::env::temp_dir().join(format!("random-background-scan-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("Landscapes/old")).unwrap();
+        std::fs::create_dir_all(dir.join("Working")).unwrap();
+        for file in [
+            "top.jpg",
+            "Landscapes/hills.jpg",
+            "Landscapes/old/lake.jpg",
+            "Working/current.png",
+        ] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        assert_eq!(
+            keys(&dir, ""),
+            ["Landscapes/hills.jpg", "Landscapes/old/lake.jpg", "top.jpg"]
+        );
+        assert_eq!(
+            keys(&dir, "include = ['Landscapes/*.jpg']"),
+            ["Landscapes/hills.jpg"]
+        );
+        assert_eq!(
+            keys(&dir, "include = ['Landscapes/**']"),
+            ["Landscapes/hills.jpg", "Landscapes/old/lake.jpg"]
+        );
+        assert_eq!(
+            keys(&dir, "exclude = ['*']"),
+            ["Landscapes/hills.jpg", "Landscapes/old/lake.jpg"]
+        );
+        assert_eq!(keys(&dir, "recursive = false"), ["top.jpg"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
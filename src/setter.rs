@@ -0,0 +1,348 @@
+use std::{
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, bail, Context};
+use image::{Rgb, RgbImage};
+
+use crate::{
+    config::{ConfigSetter, SetterBackend},
+    render::RenderedWallpaper,
+};
+
+/// Sets rendered wallpapers as the desktop background.
+pub(crate) trait WallpaperSetter {
+    fn set(&self, wallpapers: &[RenderedWallpaper]) -> anyhow::Result<()>;
+
+    /// Whether the backend only notices a new wallpaper if it was set to a different image first
+    /// (as the file name of the rendered wallpaper never changes).
+    fn refreshes_with_blank_by_default(&self) -> bool {
+        false
+    }
+}
+
+/// Uses the `wallpaper` crate, which supports most desktop environments.
+struct WallpaperCrateSetter;
+
+impl WallpaperSetter for WallpaperCrateSetter {
+    fn set(&self, wallpapers: &[RenderedWallpaper]) -> anyhow::Result<()> {
+        if wallpapers.len() > 1 {
+            log::warn!(
+                "The wallpaper_crate backend can only set one wallpaper; setting {}",
+                wallpapers[0].path
+            );
+        }
+        wallpaper::set_mode(wallpaper::Mode::Crop)
+            .map_err(|e| anyhow!("Failed to set wallpaper mode: {}", e))?;
+        wallpaper::set_from_path(&wallpapers[0].path)
+            .map_err(|e| anyhow!("Failed to set wallpaper: {}", e))
+    }
+
+    fn refreshes_with_blank_by_default(&self) -> bool {
+        true
+    }
+}
+
+fn run(program: &str, args: &[String]) -> anyhow::Result<()> {
+    log::debug!("Running {} {:?}", program, args);
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {} (is it installed?)", program))?;
+    if !status.success() {
+        bail!("{} {:?} failed with {}", program, args, status);
+    }
+    Ok(())
+}
+
+/// The `swaybg` spawned by the last [`SwaybgSetter::set`], which must be reaped once replaced
+/// (`refresh` and `daemon` set the wallpapers many times in one process).
+static SWAYBG: Mutex<Option<Child>> = Mutex::new(None);
+
+/// Runs the `swaybg` daemon (sway and other wlroots compositors), replacing any running instance.
+struct SwaybgSetter;
+
+impl WallpaperSetter for SwaybgSetter {
+    fn set(&self, wallpapers: &[RenderedWallpaper]) -> anyhow::Result<()> {
+        let mut swaybg = SWAYBG.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(mut child) = swaybg.take() {
+            // kill fails if it has already exited, in which case wait just reaps it
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        // Replaces instances started by other processes, e.g. an earlier run of this program.
+        // pkill fails if there was no swaybg running, which is fine
+        let _ = Command::new("pkill").args(["-x", "swaybg"]).status();
+        let mut args = Vec::new();
+        for wallpaper in wallpapers {
+            let output = wallpaper.monitor.clone().unwrap_or(String::from("*"));
+            args.extend([
+                String::from("-o"),
+                output,
+                String::from("-i"),
+                wallpaper.path.clone(),
+                String::from("-m"),
+                String::from("fill"),
+            ]);
+        }
+        log::debug!("Spawning swaybg {:?}", args);
+        let child = Command::new("swaybg")
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to run swaybg (is it installed?)")?;
+        *swaybg = Some(child);
+        Ok(())
+    }
+}
+
+/// Uses `swww`, which requires its daemon to be running already.
+struct SwwwSetter;
+
+impl WallpaperSetter for SwwwSetter {
+    fn set(&self, wallpapers: &[RenderedWallpaper]) -> anyhow::Result<()> {
+        for wallpaper in wallpapers {
+            let mut args = vec![String::from("img"), wallpaper.path.clone()];
+            if let Some(monitor) = &wallpaper.monitor {
+                args.extend([String::from("--outputs"), monitor.clone()]);
+            }
+            run("swww", &args)?;
+        }
+        Ok(())
+    }
+}
+
+/// Uses `feh` (bare X and most X window managers). Per-monitor wallpapers are assigned to
+/// Xinerama screens in the order the monitors are declared.
+struct FehSetter;
+
+impl WallpaperSetter for FehSetter {
+    fn set(&self, wallpapers: &[RenderedWallpaper]) -> anyhow::Result<()> {
+        let mut args = vec![String::from("--bg-fill")];
+        if wallpapers
+            .iter()
+            .all(|wallpaper| wallpaper.monitor.is_none())
+        {
+            args.push(String::from("--no-xinerama"));
+        }
+        args.extend(wallpapers.iter().map(|wallpaper| wallpaper.path.clone()));
+        run("feh", &args)
+    }
+}
+
+/// Uses `hyprpaper` through `hyprctl` (Hyprland), which requires hyprpaper to be running already.
+struct HyprpaperSetter;
+
+impl WallpaperSetter for HyprpaperSetter {
+    fn set(&self, wallpapers: &[RenderedWallpaper]) -> anyhow::Result<()> {
+        // hyprpaper caches images by path, so the previous wallpaper must be unloaded first
+        run(
+            "hyprctl",
+            &[
+                String::from("hyprpaper"),
+                String::from("unload"),
+                String::from("all"),
+            ],
+        )?;
+        for wallpaper in wallpapers {
+            run(
+                "hyprctl",
+                &[
+                    String::from("hyprpaper"),
+                    String::from("preload"),
+                    wallpaper.path.clone(),
+                ],
+            )?;
+            run(
+                "hyprctl",
+                &[
+                    String::from("hyprpaper"),
+                    String::from("wallpaper"),
+                    format!(
+                        "{},{}",
+                        wallpaper.monitor.clone().unwrap_or_default(),
+                        wallpaper.path
+                    ),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a user-defined command once per wallpaper. The command template is split on whitespace
+/// (no shell is involved), then `{path}` and `{monitor}` are substituted in each argument.
+struct CommandSetter {
+    template: Vec<String>,
+}
+
+impl CommandSetter {
+    /// The program and arguments to run for `wallpaper`.
+    fn command(&self, wallpaper: &RenderedWallpaper) -> anyhow::Result<(String, Vec<String>)> {
+        let monitor = wallpaper.monitor.clone().unwrap_or_default();
+        let mut args = self.template.iter().map(|arg| {
+            arg.replace("{path}", &wallpaper.path)
+                .replace("{monitor}", &monitor)
+        });
+        let program = args.next().ok_or(anyhow!("The setter command is empty"))?;
+        Ok((program, args.collect()))
+    }
+}
+
+impl WallpaperSetter for CommandSetter {
+    fn set(&self, wallpapers: &[RenderedWallpaper]) -> anyhow::Result<()> {
+        for wallpaper in wallpapers {
+            let (program, args) = self.command(wallpaper)?;
+            run(&program, &args)?;
+        }
+        Ok(())
+    }
+}
+
+fn setter_from_config(config: &ConfigSetter) -> anyhow::Result<Box<dyn WallpaperSetter>> {
+    Ok(match config.backend {
+        SetterBackend::WallpaperCrate => Box::new(WallpaperCrateSetter),
+        SetterBackend::Swaybg => Box::new(SwaybgSetter),
+        SetterBackend::Swww => Box::new(SwwwSetter),
+        SetterBackend::Feh => Box::new(FehSetter),
+        SetterBackend::Hyprpaper => Box::new(HyprpaperSetter),
+        SetterBackend::Command => Box::new(command_setter(config)?),
+    })
+}
+
+fn command_setter(config: &ConfigSetter) -> anyhow::Result<CommandSetter> {
+    let template: Vec<String> = config
+        .command
+        .as_deref()
+        .ok_or(anyhow!(
+            "[setter] command must be specified when backend = 'command'"
+        ))?
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    if template.is_empty() {
+        bail!("[setter] command must not be empty");
+    }
+    Ok(CommandSetter { template })
+}
+
+fn ensure_blank_background_exists(blank_wallpaper_path: &str) -> anyhow::Result<()> {
+    RgbImage::from_pixel(1, 1, Rgb([0, 0, 0]))
+        .save(blank_wallpaper_path)
+        .with_context(|| format!("Failed to save blank wallpaper to {}", blank_wallpaper_path))?;
+    Ok(())
+}
+
+/// Sets the rendered wallpapers with the backend chosen in `[setter]`.
+pub(crate) fn set_wallpapers(
+    parent_dir: &str,
+    config: &ConfigSetter,
+    wallpapers: &[RenderedWallpaper],
+) -> anyhow::Result<()> {
+    let setter = setter_from_config(config)?;
+    let refresh_with_blank = config
+        .refresh_with_blank
+        .unwrap_or(setter.refreshes_with_blank_by_default());
+    if refresh_with_blank {
+        // Set a blank image first, so that the backend notices the wallpaper has changed
+        let blank_wallpaper_path: PathBuf = [parent_dir, "Working", "blank.png"].iter().collect();
+        let blank_wallpaper_path = blank_wallpaper_path.to_string_lossy().to_string();
+        ensure_blank_background_exists(&blank_wallpaper_path)?;
+        let blank_wallpapers: Vec<RenderedWallpaper> = wallpapers
+            .iter()
+            .map(|wallpaper| RenderedWallpaper {
+                monitor: wallpaper.monitor.clone(),
+                path: blank_wallpaper_path.clone(),
+            })
+            .collect();
+        setter.set(&blank_wallpapers)?;
+    }
+    setter.set(wallpapers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setter_config(toml: &str) -> ConfigSetter {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn wallpaper(monitor: Option<&str>, path: &str) -> RenderedWallpaper {
+        RenderedWallpaper {
+            monitor: monitor.map(String::from),
+            path: String::from(path),
+        }
+    }
+
+    #[test]
+    fn chooses_the_configured_backend() {
+        let default = setter_from_config(&setter_config("")).unwrap();
+        assert!(default.refreshes_with_blank_by_default());
+        for backend in ["swaybg", "swww", "feh", "hyprpaper"] {
+            let setter =
+                setter_from_config(&setter_config(&format!("backend = '{}'", backend))).unwrap();
+            assert!(!setter.refreshes_with_blank_by_default(), "{}", backend);
+        }
+    }
+
+    #[test]
+    fn command_must_not_be_empty() {
+        for toml in [
+            "backend = 'command'",
+            "backend = 'command'\ncommand = ''",
+            "backend = 'command'\ncommand = '  '",
+        ] {
+            assert!(
+                setter_from_config(&setter_config(toml)).is_err(),
+                "{}",
+                toml
+            );
+        }
+    }
+
+    #[test]
+    fn substitutes_path_and_monitor() {
+        let setter = command_setter(&setter_config(
+            "backend = 'command'\ncommand = 'set-bg  --output={monitor} {path}'",
+        ))
+        .unwrap();
+        assert_eq!(
+            setter
+                .command(&wallpaper(
+                    Some("DP-1"),
+                    "/home/me/My Pictures/current-DP-1.png"
+                ))
+                .unwrap(),
+            (
+                String::from("set-bg"),
+                vec![
+                    String::from("--output=DP-1"),
+                    // Substituted after splitting, so a path with spaces stays one argument
+                    String::from("/home/me/My Pictures/current-DP-1.png"),
+                ]
+            )
+        );
+        assert_eq!(
+            setter.command(&wallpaper(None, "current.png")).unwrap(),
+            (
+                String::from("set-bg"),
+                vec![String::from("--output="), String::from("current.png")]
+            )
+        );
+    }
+
+    #[test]
+    fn command_is_not_unquoted() {
+        let setter = command_setter(&setter_config(
+            "backend = 'command'\ncommand = \"feh --bg-fill '{path}'\"",
+        ))
+        .unwrap();
+        let (_, args) = setter.command(&wallpaper(None, "a b.png")).unwrap();
+        assert_eq!(args, vec!["--bg-fill", "'a b.png'"]);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::Context;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ShuffleBagState {
+    /// Images still to be shown in the current cycle, next pick first
+    remaining: Vec<String>,
+    /// Images already shown in the current cycle
+    shown: Vec<String>,
+}
+
+/// Shuffled queue of images that is worked through before any image repeats, persisted in
+/// `<DIR>/Working/shuffle_bag.toml`.
+#[derive(Debug)]
+pub(crate) struct ShuffleBag {
+    path: PathBuf,
+    state: ShuffleBagState,
+}
+
+impl ShuffleBag {
+    pub(crate) fn read_from_dir(parent_dir: &str) -> anyhow::Result<Self> {
+        let path: PathBuf = [parent_dir, "Working", "shuffle_bag.toml"].iter().collect();
+        let state = if path.exists() {
+            let toml_str = std::fs::read_to_string(&path).with_context(|| {
+                format!("Failed to read shuffle bag from {}", path.to_string_lossy())
+            })?;
+            toml::from_str(&toml_str).with_context(|| {
+                format!(
+                    "Failed to parse shuffle bag at {}. (Deleting it starts a new cycle.)",
+                    path.to_string_lossy()
+                )
+            })?
+        } else {
+            ShuffleBagState::default()
+        };
+        Ok(Self { path, state })
+    }
+
+    /// Brings the bag in line with the images currently available, without restarting the cycle.
+    /// Removed images are pruned, and new images are inserted at random positions.
+    pub(crate) fn sync(&mut self, available: &[String], rng: &mut impl Rng) {
+        let available_set: HashSet<&str> = available.iter().map(String::as_str).collect();
+        let state = &mut self.state;
+        state
+            .remaining
+            .retain(|image_name| available_set.contains(image_name.as_str()));
+        state
+            .shown
+            .retain(|image_name| available_set.contains(image_name.as_str()));
+        let known: HashSet<String> = state
+            .remaining
+            .iter()
+            .chain(state.shown.iter())
+            .cloned()
+            .collect();
+        for image_name in available {
+            if !known.contains(image_name) {
+                log::debug!("Adding new image {} to shuffle bag", image_name);
+                let position = rng.gen_range(0..=state.remaining.len());
+                state.remaining.insert(position, image_name.clone());
+            }
+        }
+    }
+
+    /// Takes the next image out of the bag. Once every image has been shown, a new cycle is
+    /// started; images in `recent` (most recent first) are moved to the back of it so that they
+    /// don't reappear straight away.
+    pub(crate) fn pop(
+        &mut self,
+        available: &[String],
+        recent: &[&str],
+        rng: &mut impl Rng,
+    ) -> Option<String> {
+        let state = &mut self.state;
+        if state.remaining.is_empty() {
+            log::info!(
+                "Shuffle bag exhausted; reshuffling {} image(s)",
+                available.len()
+            );
+            state.shown.clear();
+            state.remaining = available.to_vec();
+            state.remaining.shuffle(rng);
+            // Stable sort: images not shown recently keep their shuffled order at the front
+            state.remaining.sort_by_key(|image_name| {
+                recent
+                    .iter()
+                    .position(|recent_name| recent_name == image_name)
+                    .map_or(0, |position| recent.len() - position)
+            });
+        }
+        if state.remaining.is_empty() {
+            return None;
+        }
+        let image_name = state.remaining.remove(0);
+        state.shown.push(image_name.clone());
+        log::debug!(
+            "Shuffle bag: {} shown, {} remaining in this cycle",
+            state.shown.len(),
+            state.remaining.len()
+        );
+        Some(image_name)
+    }
+
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let toml_str = toml::to_string(&self.state)?;
+        std::fs::write(&self.path, toml_str).with_context(|| {
+            format!(
+                "Failed to save shuffle bag to {}",
+                self.path.to_string_lossy()
+            )
+        })
+    }
+}
@@ -0,0 +1,269 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Context;
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+use serde::Deserialize;
+
+use crate::{config::CropStrategy, imageops::Screen};
+
+/// Images are downscaled so that their fixed axis is this long before scoring crop windows.
+const SCORING_SIZE_PX: u32 = 256;
+/// Number of luma buckets used when computing entropy.
+const ENTROPY_BUCKETS: usize = 32;
+
+/// Point of interest of an image, as fractions of its width and height (0.0 is left/top, 1.0 is
+/// right/bottom).
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub(crate) struct FocalPoint {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
+/// Manually chosen focal points, keyed by image name, read from `<DIR>/Working/focal_points.toml`.
+#[derive(Debug, Default)]
+pub(crate) struct FocalPoints {
+    points: BTreeMap<String, FocalPoint>,
+}
+
+impl FocalPoints {
+    pub(crate) fn read_from_dir(parent_dir: &str) -> anyhow::Result<Self> {
+        let path: PathBuf = [parent_dir, "Working", "focal_points.toml"]
+            .iter()
+            .collect();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let toml_str = std::fs::read_to_string(&path).with_context(|| {
+            format!(
+                "Failed to read focal points from {}",
+                path.to_string_lossy()
+            )
+        })?;
+        let points = toml::from_str(&toml_str)
+            .with_context(|| format!("Please fix the TOML file at {}", path.to_string_lossy()))?;
+        Ok(Self { points })
+    }
+
+    /// How the given image should be cropped: a manual focal point always wins over `strategy`.
+    pub(crate) fn crop_for(&self, image_name: &str, strategy: CropStrategy) -> Crop {
+        match self.points.get(image_name) {
+            Some(focal_point) => Crop::FocalPoint(*focal_point),
+            None => Crop::Strategy(strategy),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Crop {
+    FocalPoint(FocalPoint),
+    Strategy(CropStrategy),
+}
+
+/// Offset along the free axis of the window that is kept when cropping `img` to `screen`.
+/// `img` must already fit `screen` exactly along the other axis.
+pub(crate) fn crop_offset(img: &DynamicImage, screen: Screen, crop: Crop) -> u32 {
+    let horizontal = img.width() > screen.width_px;
+    let (length, window) = if horizontal {
+        (img.width(), screen.width_px)
+    } else {
+        (img.height(), screen.height_px)
+    };
+    let slack = length - window;
+    match crop {
+        Crop::Strategy(CropStrategy::Centre) => length / 2 - window / 2,
+        Crop::FocalPoint(focal_point) => {
+            let fraction = if horizontal {
+                focal_point.x
+            } else {
+                focal_point.y
+            };
+            let centre = fraction.clamp(0.0, 1.0) * length as f64;
+            (centre - window as f64 / 2.0)
+                .clamp(0.0, slack as f64)
+                .round() as u32
+        }
+        Crop::Strategy(strategy) => best_scoring_offset(img, horizontal, window, slack, strategy),
+    }
+}
+
+/// Slides a window along the free axis of a downscaled copy of `img`, and returns the offset of
+/// the window with the highest score, scaled back to `img`.
+fn best_scoring_offset(
+    img: &DynamicImage,
+    horizontal: bool,
+    window: u32,
+    slack: u32,
+    strategy: CropStrategy,
+) -> u32 {
+    let fixed_length = if horizontal {
+        img.height()
+    } else {
+        img.width()
+    };
+    let factor = (SCORING_SIZE_PX as f64 / fixed_length as f64).min(1.0);
+    let small = img
+        .resize_exact(
+            ((img.width() as f64 * factor).round() as u32).max(1),
+            ((img.height() as f64 * factor).round() as u32).max(1),
+            FilterType::Triangle,
+        )
+        .to_luma8();
+    // Work on columns when the free axis is horizontal, on rows otherwise. Rotating clockwise
+    // would reverse the order of the rows, so row y becomes column y by rotating anticlockwise.
+    let small = if horizontal {
+        small
+    } else {
+        image::imageops::rotate270(&small)
+    };
+    let small_window = ((window as f64 * factor).round() as usize).clamp(1, small.width() as usize);
+    let starts = 0..=(small.width() as usize - small_window);
+
+    let best_start = match strategy {
+        CropStrategy::EdgeDensity => {
+            let column_scores = edge_density_per_column(&small);
+            let mut score: u64 = column_scores[..small_window].iter().sum();
+            let mut best = (score, 0);
+            for start in starts.skip(1) {
+                score = score + column_scores[start + small_window - 1] - column_scores[start - 1];
+                if score > best.0 {
+                    best = (score, start);
+                }
+            }
+            best.1
+        }
+        CropStrategy::Entropy => {
+            let column_histograms = histogram_per_column(&small);
+            let mut histogram = [0u64; ENTROPY_BUCKETS];
+            for column_histogram in &column_histograms[..small_window] {
+                add_histogram(&mut histogram, column_histogram, true);
+            }
+            let mut best = (entropy(&histogram), 0);
+            for start in starts.skip(1) {
+                add_histogram(&mut histogram, &column_histograms[start - 1], false);
+                add_histogram(
+                    &mut histogram,
+                    &column_histograms[start + small_window - 1],
+                    true,
+                );
+                let score = entropy(&histogram);
+                if score > best.0 {
+                    best = (score, start);
+                }
+            }
+            best.1
+        }
+        CropStrategy::Centre => unreachable!("centre crops are not scored"),
+    };
+    log::debug!(
+        "Smart crop ({:?}): best window starts at {} of {} (downscaled)",
+        strategy,
+        best_start,
+        small.width()
+    );
+    ((best_start as f64 / factor).round() as u32).min(slack)
+}
+
+fn edge_density_per_column(img: &GrayImage) -> Vec<u64> {
+    let gradients = imageproc::gradients::sobel_gradients(img);
+    (0..gradients.width())
+        .map(|x| {
+            (0..gradients.height())
+                .map(|y| gradients.get_pixel(x, y).0[0] as u64)
+                .sum()
+        })
+        .collect()
+}
+
+fn histogram_per_column(img: &GrayImage) -> Vec<[u64; ENTROPY_BUCKETS]> {
+    (0..img.width())
+        .map(|x| {
+            let mut histogram = [0u64; ENTROPY_BUCKETS];
+            for y in 0..img.height() {
+                histogram[img.get_pixel(x, y).0[0] as usize * ENTROPY_BUCKETS / 256] += 1;
+            }
+            histogram
+        })
+        .collect()
+}
+
+fn add_histogram(
+    histogram: &mut [u64; ENTROPY_BUCKETS],
+    other: &[u64; ENTROPY_BUCKETS],
+    add: bool,
+) {
+    for (count, other_count) in histogram.iter_mut().zip(other.iter()) {
+        if add {
+            *count += other_count;
+        } else {
+            *count -= other_count;
+        }
+    }
+}
+
+/// Shannon entropy (in bits) of a histogram.
+fn entropy(histogram: &[u64; ENTROPY_BUCKETS]) -> f64 {
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// A flat grey image with a checkerboard of 10 px squares from `detail_start` to
+    /// `detail_start + 100` along its long axis.
+    fn image_with_detail(width: u32, height: u32, detail_start: u32) -> DynamicImage {
+        let horizontal = width > height;
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            let along = if horizontal { x } else { y };
+            if (detail_start..detail_start + 100).contains(&along) && (x / 10 + y / 10) % 2 == 0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([128, 128, 128, 255])
+            }
+        }))
+    }
+
+    fn assert_window_over_detail(width: u32, height: u32, detail_start: u32) {
+        let img = image_with_detail(width, height, detail_start);
+        let screen = Screen {
+            width_px: 200,
+            height_px: 200,
+        };
+        for strategy in [CropStrategy::EdgeDensity, CropStrategy::Entropy] {
+            let offset = crop_offset(&img, screen, Crop::Strategy(strategy));
+            assert!(
+                offset <= detail_start && detail_start + 100 <= offset + 200,
+                "{:?} crop of {}x{} at {} misses the detail at {}",
+                strategy,
+                width,
+                height,
+                offset,
+                detail_start
+            );
+        }
+    }
+
+    #[test]
+    fn crops_wide_images_to_the_detail() {
+        assert_window_over_detail(800, 200, 20);
+        assert_window_over_detail(800, 200, 650);
+    }
+
+    #[test]
+    fn crops_tall_images_to_the_detail() {
+        assert_window_over_detail(200, 800, 20);
+        assert_window_over_detail(200, 800, 650);
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::bail;
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+
+/// Which countdown a template is for, as `{exam_day}`, `{exam_days}`, `{week}` and
+/// `{teaching_weeks}` only make sense for the `[countdown]` exam term.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TemplateKind {
+    Term,
+    Event,
+}
+
+/// What the placeholders of a template are replaced with.
+#[derive(Debug, Clone)]
+pub(crate) struct TemplateValues<'a> {
+    /// Days left until the target day (in the countdown's counting)
+    pub(crate) days: i64,
+    /// Calendar days left, from which `{weeks}` is derived
+    pub(crate) calendar_days: i64,
+    pub(crate) hours: i64,
+    /// Minutes left, rounded up, from which `{clock}` is derived
+    pub(crate) minutes: i64,
+    pub(crate) phase: &'a str,
+    pub(crate) event: &'a str,
+    /// Day of the exams (from 1), or 0 outside them
+    pub(crate) exam_day: i64,
+    pub(crate) exam_days: i64,
+    /// Teaching week (from 1), or the last one before a break, or 0 outside teaching
+    pub(crate) week: i64,
+    pub(crate) teaching_weeks: i64,
+    pub(crate) now: DateTime<Local>,
+    pub(crate) target: DateTime<Local>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Days,
+    Weeks,
+    Hours,
+    Minutes,
+    Clock,
+    Phase,
+    Event,
+    ExamDay,
+    ExamDays,
+    Week,
+    TeachingWeeks,
+    Now(String),
+    Target(String),
+}
+
+/// A format string such as `'Exam day {exam_day}/{exam_days}'` or `'{now:%a %-d %b}'`. Braces
+/// are escaped by doubling them.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Template {
+    segments: Vec<Segment>,
+}
+
+fn parse_placeholder(placeholder: &str, kind: TemplateKind) -> anyhow::Result<Segment> {
+    if let Some((date, format)) = placeholder.split_once(':') {
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            bail!("Invalid strftime pattern {}", format);
+        }
+        return match date {
+            "now" => Ok(Segment::Now(String::from(format))),
+            "target" => Ok(Segment::Target(String::from(format))),
+            _ => bail!("Unknown date {}; expected now or target", date),
+        };
+    }
+    match (placeholder, kind) {
+        ("days", _) => Ok(Segment::Days),
+        ("weeks", _) => Ok(Segment::Weeks),
+        ("hours", _) => Ok(Segment::Hours),
+        ("minutes", _) => Ok(Segment::Minutes),
+        ("clock", _) => Ok(Segment::Clock),
+        ("phase", _) => Ok(Segment::Phase),
+        ("event", _) => Ok(Segment::Event),
+        ("exam_day", TemplateKind::Term) => Ok(Segment::ExamDay),
+        ("exam_days", TemplateKind::Term) => Ok(Segment::ExamDays),
+        ("week", TemplateKind::Term) => Ok(Segment::Week),
+        ("teaching_weeks", TemplateKind::Term) => Ok(Segment::TeachingWeeks),
+        ("exam_day" | "exam_days" | "week" | "teaching_weeks", TemplateKind::Event) => {
+            bail!("{{{}}} is only available in [countdown]", placeholder)
+        }
+        _ => bail!("Unknown placeholder {{{}}}", placeholder),
+    }
+}
+
+impl Template {
+    pub(crate) fn parse(template: &str, kind: TemplateKind) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => bail!("Unclosed {{ in template {}", template),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_placeholder(placeholder.trim(), kind)?);
+                }
+                '}' => bail!(
+                    "Unmatched }} in template {}; write }}}} for a brace",
+                    template
+                ),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    pub(crate) fn render(&self, values: &TemplateValues) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.clone(),
+                Segment::Days => values.days.to_string(),
+                Segment::Weeks => values.calendar_days.div_euclid(7).to_string(),
+                Segment::Hours => values.hours.to_string(),
+                Segment::Minutes => values.minutes.to_string(),
+                Segment::Clock => format!(
+                    "{}:{:02}",
+                    values.minutes.div_euclid(60),
+                    values.minutes.rem_euclid(60)
+                ),
+                Segment::Phase => String::from(values.phase),
+                Segment::Event => String::from(values.event),
+                Segment::ExamDay => values.exam_day.to_string(),
+                Segment::ExamDays => values.exam_days.to_string(),
+                Segment::Week => values.week.to_string(),
+                Segment::TeachingWeeks => values.teaching_weeks.to_string(),
+                Segment::Now(format) => values.now.format(format).to_string(),
+                Segment::Target(format) => values.target.format(format).to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dateutils::parse_local_datetime;
+
+    fn values() -> TemplateValues<'static> {
+        TemplateValues {
+            days: 12,
+            calendar_days: 16,
+            hours: 380,
+            minutes: 22_747,
+            phase: "term",
+            event: "Launch",
+            exam_day: 0,
+            exam_days: 7,
+            week: 5,
+            teaching_weeks: 8,
+            now: parse_local_datetime("2024-06-03T09:00:00").unwrap(),
+            target: parse_local_datetime("2024-06-19T12:00:00").unwrap(),
+        }
+    }
+
+    fn render(template: &str) -> String {
+        Template::parse(template, TemplateKind::Term)
+            .unwrap()
+            .render(&values())
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        assert_eq!(render("{event} in {days} days"), "Launch in 12 days");
+        assert_eq!(render("{weeks}w, <{hours}h ({phase})"), "2w, <380h (term)");
+        assert_eq!(render("Exam day {exam_day}/{exam_days}"), "Exam day 0/7");
+        assert_eq!(render("Week {week} of {teaching_weeks}"), "Week 5 of 8");
+        assert_eq!(
+            render("{minutes} min, {clock} left"),
+            "22747 min, 379:07 left"
+        );
+    }
+
+    #[test]
+    fn renders_dates_and_escaped_braces() {
+        assert_eq!(
+            render("{now:%a %-d %b} to {target:%-d/%m %H:%M}"),
+            "Mon 3 Jun to 19/06 12:00"
+        );
+        assert_eq!(render("{{days}} }}"), "{days} }");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for template in ["{seconds}", "{days", "days}", "{now:%Q}", "{then:%a}"] {
+            assert!(
+                Template::parse(template, TemplateKind::Term).is_err(),
+                "{}",
+                template
+            );
+        }
+        assert!(Template::parse("{exam_day}", TemplateKind::Event).is_err());
+        assert!(Template::parse("{week}", TemplateKind::Event).is_err());
+    }
+}
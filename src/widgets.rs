@@ -0,0 +1,339 @@
+use chrono::{Datelike, Months, NaiveDate};
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_text_mut, text_size};
+use rusttype::{Font, Scale};
+
+use crate::{imageops::Screen, imageutils::blend_rect_mut};
+
+/// How a countdown is drawn above its caption.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Widget {
+    /// The value, in large text
+    Number,
+    ProgressBar(ProgressBar),
+    Calendar(CalendarGrid),
+    Timetable(Timetable),
+}
+
+/// Translucent backing of the widgets, so that they can be read over any image.
+const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 127]);
+
+/// A horizontal bar, filled up to `progress`, with a tick at each of the `markers`. Positions are
+/// fractions from 0 (the left end) to 1 (the right end).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ProgressBar {
+    pub(crate) progress: f64,
+    pub(crate) markers: Vec<f64>,
+}
+
+impl ProgressBar {
+    pub(crate) fn size(&self, screen: Screen) -> (i32, i32) {
+        // Markers stick out above and below the bar
+        (screen.scale_px(640), screen.scale_px(36 + 2 * 10))
+    }
+
+    pub(crate) fn draw(
+        &self,
+        img: &mut RgbaImage,
+        (x, y): (i32, i32),
+        screen: Screen,
+        fill_colour: Rgba<u8>,
+        stroke_colour: Rgba<u8>,
+    ) {
+        let (width, height) = self.size(screen);
+        let overhang = screen.scale_px(10);
+        let bar_y = y + overhang;
+        let bar_height = height - 2 * overhang;
+        let border = screen.scale_px(2).max(1);
+        let x_at = |position: f64| x + (position.clamp(0.0, 1.0) * width as f64).round() as i32;
+
+        blend_rect_mut(img, (x, bar_y), (width, bar_height), BACKGROUND);
+        blend_rect_mut(
+            img,
+            (x, bar_y),
+            (x_at(self.progress) - x, bar_height),
+            fill_colour,
+        );
+        outline_rect(img, (x, bar_y), (width, bar_height), border, stroke_colour);
+        let marker_width = screen.scale_px(4).max(1);
+        let marker_colour = Rgba([stroke_colour[0], stroke_colour[1], stroke_colour[2], 255]);
+        for marker in &self.markers {
+            blend_rect_mut(
+                img,
+                (x_at(*marker) - marker_width / 2, y),
+                (marker_width, height),
+                marker_colour,
+            );
+        }
+    }
+}
+
+/// Month grids from the month of `today`, with the days before `today` greyed out, and the
+/// `marked` days outlined.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CalendarGrid {
+    pub(crate) today: NaiveDate,
+    pub(crate) marked: Vec<NaiveDate>,
+    /// First day of each month shown
+    months: Vec<NaiveDate>,
+}
+
+/// At most this many months are shown side by side.
+const MAX_CALENDAR_MONTHS: u32 = 3;
+/// Rows of a month: its name, the weekday initials, then up to six weeks.
+const CALENDAR_ROWS: i32 = 8;
+
+impl CalendarGrid {
+    /// Shows the months from `today` until the next marked day (if any), up to a limit.
+    pub(crate) fn new(today: NaiveDate, marked: Vec<NaiveDate>) -> Self {
+        let first_month = today.with_day(1).unwrap();
+        let month_index = |date: NaiveDate| date.year() * 12 + date.month0() as i32;
+        let months_shown = marked
+            .iter()
+            .filter(|date| **date >= today)
+            .min()
+            .map_or(1, |next| month_index(*next) - month_index(today) + 1)
+            .clamp(1, MAX_CALENDAR_MONTHS as i32) as u32;
+        let months = (0..months_shown)
+            .filter_map(|i| first_month.checked_add_months(Months::new(i)))
+            .collect();
+        CalendarGrid {
+            today,
+            marked,
+            months,
+        }
+    }
+
+    fn cell_px(screen: Screen) -> i32 {
+        screen.scale_px(36)
+    }
+
+    pub(crate) fn size(&self, screen: Screen) -> (i32, i32) {
+        let cell = Self::cell_px(screen);
+        let months = self.months.len() as i32;
+        // Half a cell of padding around the grids, and a cell between months
+        (months * 8 * cell, (CALENDAR_ROWS + 1) * cell)
+    }
+
+    pub(crate) fn draw(
+        &self,
+        img: &mut RgbaImage,
+        (x, y): (i32, i32),
+        screen: Screen,
+        font: &Font,
+        fill_colour: Rgba<u8>,
+    ) {
+        let cell = Self::cell_px(screen);
+        let padding = cell / 2;
+        let font_scale = Scale::uniform(screen.scale(20.0));
+        let border = screen.scale_px(2).max(1);
+        blend_rect_mut(img, (x, y), self.size(screen), BACKGROUND);
+
+        let draw_centred = |img: &mut RgbaImage, text: &str, (cell_x, cell_y), colour| {
+            let (text_width, text_height) = text_size(font_scale, font, text);
+            draw_text_mut(
+                img,
+                colour,
+                cell_x + (cell - text_width) / 2,
+                cell_y + (cell - text_height) / 2,
+                font_scale,
+                font,
+                text,
+            );
+        };
+        let white = Rgba([255; 4]);
+        for (i, month) in self.months.iter().enumerate() {
+            let month_x = x + padding + i as i32 * 8 * cell;
+            // Centred over the middle column, and so over the month
+            draw_centred(
+                img,
+                &month.format("%B %Y").to_string(),
+                (month_x + 3 * cell, y + padding),
+                white,
+            );
+            for (column, initial) in ["M", "T", "W", "T", "F", "S", "S"].iter().enumerate() {
+                draw_centred(
+                    img,
+                    initial,
+                    (month_x + column as i32 * cell, y + padding + cell),
+                    white,
+                );
+            }
+
+            let offset = month.weekday().num_days_from_monday() as i32;
+            for date in month
+                .iter_days()
+                .take_while(|date| date.month() == month.month())
+            {
+                let index = offset + date.day0() as i32;
+                let cell_x = month_x + index % 7 * cell;
+                let cell_y = y + padding + (2 + index / 7) * cell;
+                let text_colour = if date == self.today {
+                    blend_rect_mut(img, (cell_x, cell_y), (cell, cell), fill_colour);
+                    contrasting_text_colour(fill_colour)
+                } else if date < self.today {
+                    Rgba([128, 128, 128, 255])
+                } else {
+                    white
+                };
+                if self.marked.contains(&date) {
+                    let marker = Rgba([255, 0, 0, 255]);
+                    outline_rect(img, (cell_x, cell_y), (cell, cell), border, marker);
+                }
+                draw_centred(img, &date.day().to_string(), (cell_x, cell_y), text_colour);
+            }
+        }
+    }
+}
+
+/// Whether a paper in the timetable is done, today (and not yet done), or later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PaperState {
+    Done,
+    Today,
+    Upcoming,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TimetableRow {
+    pub(crate) cells: Vec<String>,
+    pub(crate) state: PaperState,
+}
+
+/// A table of papers, one per row, with the done papers dimmed and today's paper highlighted.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Timetable {
+    pub(crate) rows: Vec<TimetableRow>,
+}
+
+impl Timetable {
+    fn font_scale(screen: Screen) -> Scale {
+        Scale::uniform(screen.scale(20.0))
+    }
+
+    /// Widths of the columns, each as wide as its widest cell.
+    fn column_widths(&self, screen: Screen, font: &Font) -> Vec<i32> {
+        let columns = self
+            .rows
+            .iter()
+            .map(|row| row.cells.len())
+            .max()
+            .unwrap_or(0);
+        (0..columns)
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.cells.get(column))
+                    .map(|cell| text_size(Self::font_scale(screen), font, cell).0)
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    pub(crate) fn size(&self, screen: Screen, font: &Font) -> (i32, i32) {
+        let padding = screen.scale_px(10);
+        let column_gap = screen.scale_px(20);
+        let widths = self.column_widths(screen, font);
+        let gaps = widths.len().saturating_sub(1) as i32;
+        (
+            2 * padding + widths.iter().sum::<i32>() + gaps * column_gap,
+            2 * padding + self.rows.len() as i32 * screen.scale_px(30),
+        )
+    }
+
+    pub(crate) fn draw(
+        &self,
+        img: &mut RgbaImage,
+        (x, y): (i32, i32),
+        screen: Screen,
+        font: &Font,
+        fill_colour: Rgba<u8>,
+    ) {
+        let padding = screen.scale_px(10);
+        let column_gap = screen.scale_px(20);
+        let row_height = screen.scale_px(30);
+        let font_scale = Self::font_scale(screen);
+        let (width, _) = self.size(screen, font);
+        let widths = self.column_widths(screen, font);
+        blend_rect_mut(img, (x, y), self.size(screen, font), BACKGROUND);
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let row_y = y + padding + i as i32 * row_height;
+            let text_colour = match row.state {
+                PaperState::Done => Rgba([128, 128, 128, 255]),
+                PaperState::Today => {
+                    blend_rect_mut(img, (x, row_y), (width, row_height), fill_colour);
+                    contrasting_text_colour(fill_colour)
+                }
+                PaperState::Upcoming => Rgba([255; 4]),
+            };
+            let mut cell_x = x + padding;
+            for (cell, cell_width) in row.cells.iter().zip(&widths) {
+                draw_text_mut(
+                    img,
+                    text_colour,
+                    cell_x,
+                    row_y + (row_height - font_scale.y.round() as i32) / 2,
+                    font_scale,
+                    font,
+                    cell,
+                );
+                cell_x += cell_width + column_gap;
+            }
+        }
+    }
+}
+
+/// Blends a `border` px wide outline of `colour` just inside the rectangle.
+fn outline_rect(
+    img: &mut RgbaImage,
+    (x, y): (i32, i32),
+    (width, height): (i32, i32),
+    border: i32,
+    colour: Rgba<u8>,
+) {
+    blend_rect_mut(img, (x, y), (width, border), colour);
+    blend_rect_mut(img, (x, y + height - border), (width, border), colour);
+    blend_rect_mut(img, (x, y + border), (border, height - 2 * border), colour);
+    blend_rect_mut(
+        img,
+        (x + width - border, y + border),
+        (border, height - 2 * border),
+        colour,
+    );
+}
+
+/// Black or white, whichever is easier to read on `background`.
+fn contrasting_text_colour(background: Rgba<u8>) -> Rgba<u8> {
+    let [r, g, b, _] = background.0;
+    let luma = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+    if luma > 128.0 {
+        Rgba([0, 0, 0, 255])
+    } else {
+        Rgba([255; 4])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn calendar_shows_months_until_next_marked_day() {
+        let months = |today, marked: &[&str]| {
+            CalendarGrid::new(date(today), marked.iter().map(|d| date(d)).collect()).months
+        };
+        assert_eq!(months("2024-06-12", &[]), [date("2024-06-01")]);
+        assert_eq!(
+            months("2024-05-20", &["2024-06-17", "2024-07-05"]),
+            [date("2024-05-01"), date("2024-06-01")]
+        );
+        assert_eq!(months("2024-07-06", &["2024-07-05"]), [date("2024-07-01")]);
+        assert_eq!(months("2024-01-10", &["2024-12-25"]).len(), 3);
+    }
+}
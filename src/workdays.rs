@@ -0,0 +1,135 @@
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Context};
+use chrono::{Datelike, NaiveDate, Weekday};
+
+use crate::config::{ConfigWorkingDays, DayCounting};
+
+/// Which days are worked, for countdowns that count working days.
+#[derive(Debug)]
+pub(crate) struct WorkingDays {
+    weekend: Vec<Weekday>,
+    holidays: BTreeSet<NaiveDate>,
+}
+
+fn parse_date(s: &str) -> anyhow::Result<NaiveDate> {
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .with_context(|| format!("{} is not a date of the form YYYY-MM-DD", s.trim()))
+}
+
+/// Parses a holiday file: one date (`2024-12-25`) or inclusive range of dates
+/// (`2024-08-05..2024-08-16`) per line. Blank lines and `#` comments are ignored.
+fn parse_holiday_file(contents: &str) -> anyhow::Result<Vec<NaiveDate>> {
+    let mut holidays = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parse_line = || -> anyhow::Result<Vec<NaiveDate>> {
+            match line.split_once("..") {
+                None => Ok(vec![parse_date(line)?]),
+                Some((first, last)) => {
+                    let (first, last) = (parse_date(first)?, parse_date(last)?);
+                    if last < first {
+                        return Err(anyhow!("{} is before {}", last, first));
+                    }
+                    Ok(first.iter_days().take_while(|date| *date <= last).collect())
+                }
+            }
+        };
+        holidays.extend(parse_line().with_context(|| format!("Invalid line {}", i + 1))?);
+    }
+    Ok(holidays)
+}
+
+impl WorkingDays {
+    pub(crate) fn from_config(config: &ConfigWorkingDays) -> anyhow::Result<Self> {
+        let mut holidays = BTreeSet::new();
+        for holiday in &config.holidays {
+            holidays.insert(parse_date(&holiday.to_string())?);
+        }
+        if let Some(holiday_file) = &config.holiday_file {
+            let contents = std::fs::read_to_string(holiday_file)
+                .with_context(|| format!("Failed to read holiday file {}", holiday_file))?;
+            holidays.extend(
+                parse_holiday_file(&contents)
+                    .with_context(|| format!("Please fix the holiday file at {}", holiday_file))?,
+            );
+        }
+        Ok(WorkingDays {
+            weekend: config.weekend.clone(),
+            holidays,
+        })
+    }
+
+    fn is_working_day(&self, date: NaiveDate) -> bool {
+        !self.weekend.contains(&date.weekday()) && !self.holidays.contains(&date)
+    }
+
+    /// Number of days from `from` (inclusive) to `to` (exclusive) that are counted.
+    pub(crate) fn days_between(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        counting: DayCounting,
+    ) -> i64 {
+        match counting {
+            DayCounting::Calendar => (to - from).num_days(),
+            DayCounting::Working => from
+                .iter_days()
+                .take_while(|date| *date < to)
+                .filter(|date| self.is_working_day(*date))
+                .count() as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        parse_date(s).unwrap()
+    }
+
+    fn working_days(holidays: &str) -> WorkingDays {
+        WorkingDays {
+            weekend: vec![Weekday::Sat, Weekday::Sun],
+            holidays: parse_holiday_file(holidays).unwrap().into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn counts_weekdays_only() {
+        // Monday 3 June to Monday 17 June 2024
+        let working_days = working_days("");
+        let (from, to) = (date("2024-06-03"), date("2024-06-17"));
+        assert_eq!(
+            working_days.days_between(from, to, DayCounting::Calendar),
+            14
+        );
+        assert_eq!(
+            working_days.days_between(from, to, DayCounting::Working),
+            10
+        );
+        assert_eq!(working_days.days_between(to, from, DayCounting::Working), 0);
+    }
+
+    #[test]
+    fn skips_holidays_and_leave() {
+        let working_days =
+            working_days("# Bank holiday\n2024-06-05\n\n2024-06-10..2024-06-12  # leave\n");
+        assert_eq!(
+            working_days.days_between(date("2024-06-03"), date("2024-06-17"), DayCounting::Working),
+            6
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_holiday_lines() {
+        assert!(parse_holiday_file("2024-13-01").is_err());
+        assert!(parse_holiday_file("2024-06-12..2024-06-10").is_err());
+        assert!(parse_holiday_file("christmas").is_err());
+    }
+}